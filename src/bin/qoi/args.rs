@@ -0,0 +1,61 @@
+use std::convert::TryInto;
+
+//A tiny argument parser, options are written as --name value or --name=value
+pub struct Args {
+    args: Vec<String>,
+}
+
+impl Args {
+    pub fn new(args: Vec<String>) -> Args {
+        Args { args }
+    }
+
+    //Removes --name and its value
+    pub fn option(&mut self, name: &str) -> Result<Option<String>, String> {
+        let flag = format!("--{}", name);
+        let prefix = format!("--{}=", name);
+
+        let position = match self
+            .args
+            .iter()
+            .position(|arg| *arg == flag || arg.starts_with(&prefix))
+        {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        let arg = self.args.remove(position);
+        if let Some(value) = arg.strip_prefix(&prefix) {
+            return Ok(Some(value.to_owned()));
+        }
+        if position < self.args.len() {
+            Ok(Some(self.args.remove(position)))
+        } else {
+            Err(format!("{} needs a value", flag))
+        }
+    }
+
+    //Removes --name if it's there
+    pub fn flag(&mut self, name: &str) -> bool {
+        let flag = format!("--{}", name);
+        let len = self.args.len();
+        self.args.retain(|arg| *arg != flag);
+        self.args.len() < len
+    }
+
+    //Returns the positional arguments once every option has been taken out
+    pub fn finish(self) -> Result<Vec<String>, String> {
+        match self.args.iter().find(|arg| arg.starts_with("--")) {
+            Some(arg) => Err(format!("unknown option {}", arg)),
+            None => Ok(self.args),
+        }
+    }
+
+    //Like finish, but there has to be exactly one argument for each name
+    pub fn finish_exact<const N: usize>(self, names: [&str; N]) -> Result<[String; N], String> {
+        let args = self.finish()?;
+        if args.len() != N {
+            return Err(format!("expected arguments {}", names.join(" ")));
+        }
+        Ok(args.try_into().unwrap())
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+use image::codecs::png::{PngDecoder, PngEncoder};
+use image::{ColorType, GenericImageView, ImageDecoder, ImageEncoder};
+use rust_qoi::decoder::{decode_from_slice, QoiDecoder};
+use rust_qoi::encoder::{QoiEncoder, Threads};
+
+use crate::args::Args;
+use crate::convert::{load_any, parse_count};
+
+pub fn bench(mut args: Args) -> Result<(), String> {
+    let iterations = match args.option("iterations")? {
+        Some(iterations) => parse_count(&iterations)?,
+        None => 10,
+    };
+    let paths = args.finish()?;
+    if paths.is_empty() {
+        return Err("expected at least one image".to_owned());
+    }
+
+    for path in &paths {
+        let image = load_any(path)?;
+        let (width, height) = (image.width(), image.height());
+        let (pixels, colour_type) = if image.color().has_alpha() {
+            (image.to_rgba8().into_raw(), ColorType::Rgba8)
+        } else {
+            (image.to_rgb8().into_raw(), ColorType::Rgb8)
+        };
+        let megapixels = width as f64 * height as f64 / 1_000_000.0;
+        let error = |e| format!("failed to benchmark {}: {}", path, e);
+
+        println!("{} ({}x{}, {:?})", path, width, height, colour_type);
+
+        let mut qoi = Vec::new();
+        for (name, threads) in [
+            ("qoi encode, 1 thread", Threads::Fixed(1)),
+            ("qoi encode, all threads", Threads::Auto),
+        ] {
+            let elapsed = average_time(iterations, || {
+                qoi.clear();
+                QoiEncoder::new(&mut qoi).with_threads(threads).write_image(
+                    &pixels,
+                    width,
+                    height,
+                    colour_type,
+                )
+            })
+            .map_err(error)?;
+            report(name, megapixels, elapsed);
+        }
+
+        let mut decoded = vec![0u8; pixels.len()];
+        let elapsed = average_time(iterations, || {
+            decode_from_slice(&qoi)
+                .map(|_| ())
+                .map_err(image::ImageError::from)
+        })
+        .map_err(error)?;
+        report("qoi decode from slice", megapixels, elapsed);
+        let elapsed = average_time(iterations, || {
+            QoiDecoder::new(&qoi[..])?.read_image(&mut decoded)
+        })
+        .map_err(error)?;
+        report("qoi decode from reader", megapixels, elapsed);
+
+        let mut png = Vec::new();
+        let elapsed = average_time(iterations, || {
+            png.clear();
+            PngEncoder::new(&mut png).write_image(&pixels, width, height, colour_type)
+        })
+        .map_err(error)?;
+        report("png encode", megapixels, elapsed);
+        let elapsed = average_time(iterations, || {
+            PngDecoder::new(&png[..])?.read_image(&mut decoded)
+        })
+        .map_err(error)?;
+        report("png decode", megapixels, elapsed);
+
+        println!(
+            "  {:<24} qoi {:.2}:1, png {:.2}:1",
+            "compression ratio",
+            pixels.len() as f64 / qoi.len() as f64,
+            pixels.len() as f64 / png.len() as f64
+        );
+    }
+    Ok(())
+}
+
+//The average time f takes over a number of iterations
+fn average_time<E>(iterations: usize, mut f: impl FnMut() -> Result<(), E>) -> Result<Duration, E> {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f()?;
+    }
+    Ok(start.elapsed() / iterations as u32)
+}
+
+fn report(name: &str, megapixels: f64, time: Duration) {
+    println!(
+        "  {:<24} {:>10.3} ms {:>10.1} MP/s",
+        name,
+        time.as_secs_f64() * 1000.0,
+        megapixels / time.as_secs_f64()
+    );
+}
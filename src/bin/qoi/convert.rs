@@ -0,0 +1,141 @@
+use image::{DynamicImage, GenericImageView, ImageEncoder, ImageFormat, ImageResult};
+use rust_qoi::encoder::{EncodeReport, QoiEncoder, Quantize, Threads};
+use rust_qoi::header::ColorSpace;
+
+use crate::args::Args;
+use crate::{read_input, write_output};
+
+pub fn encode(mut args: Args) -> Result<(), String> {
+    let colour_space = colour_space_option(&mut args)?;
+    let threads = match args.option("threads")?.as_deref() {
+        None => Threads::default(),
+        Some("auto") => Threads::Auto,
+        Some(threads) => Threads::Fixed(parse_count(threads)?),
+    };
+    let max_error = match args.option("max-error")? {
+        None => 0,
+        Some(max_error) => max_error
+            .parse()
+            .map_err(|_| format!("invalid max error {}, expected 0 to 255", max_error))?,
+    };
+    let quantize = match args.option("colors")? {
+        Some(colours) => Some(Quantize {
+            colors: parse_count(&colours)?.min(256) as u16,
+            dither: args.flag("dither"),
+        }),
+        None => None,
+    };
+    let [input, output] = args.finish_exact(["<input>", "<output>"])?;
+
+    let options = EncodeOptions {
+        colour_space,
+        threads,
+        max_error,
+        quantize,
+    };
+
+    let image = load_any(&input)?;
+    let (bytes, report) =
+        encode_image(&image, &options).map_err(|e| format!("failed to encode {}: {}", input, e))?;
+
+    //This goes to stderr as the output might be stdout
+    if let Some(report) = report {
+        eprintln!("{} bytes, PSNR {:.2} dB", report.size, report.psnr);
+    }
+
+    write_output(&output, &bytes)
+}
+
+pub fn colour_space_option(args: &mut Args) -> Result<ColorSpace, String> {
+    match args.option("colorspace")?.as_deref() {
+        None | Some("srgb") => Ok(ColorSpace::Srgb),
+        Some("linear") => Ok(ColorSpace::Linear),
+        Some(other) => Err(format!(
+            "unknown colour space {}, expected srgb or linear",
+            other
+        )),
+    }
+}
+
+//Thread and job counts have to be at least 1
+pub fn parse_count(count: &str) -> Result<usize, String> {
+    count
+        .parse()
+        .ok()
+        .filter(|&count| count > 0)
+        .ok_or_else(|| format!("invalid count {}", count))
+}
+
+#[derive(Default)]
+pub struct EncodeOptions {
+    pub colour_space: ColorSpace,
+    pub threads: Threads,
+    pub max_error: u8,
+    pub quantize: Option<Quantize>,
+}
+
+//Lossy encodes also report how much was lost
+pub fn encode_image(
+    image: &DynamicImage,
+    options: &EncodeOptions,
+) -> ImageResult<(Vec<u8>, Option<EncodeReport>)> {
+    let (width, height) = (image.width(), image.height());
+
+    let mut bytes = Vec::new();
+    let mut encoder = QoiEncoder::new(&mut bytes)
+        .with_color_space(options.colour_space)
+        .with_threads(options.threads)
+        .with_max_error(options.max_error);
+    if let Some(quantize) = options.quantize {
+        encoder = encoder.with_quantize(quantize);
+    }
+    //QOI only has RGB and RGBA, so everything else is converted to whichever keeps the alpha
+    let (buf, color_type) = if image.color().has_alpha() {
+        (image.to_rgba8().into_raw(), image::ColorType::Rgba8)
+    } else {
+        (image.to_rgb8().into_raw(), image::ColorType::Rgb8)
+    };
+    let report = if options.max_error > 0 || options.quantize.is_some() {
+        Some(encoder.write_image_with_report(&buf, width, height, color_type)?)
+    } else {
+        encoder.write_image(&buf, width, height, color_type)?;
+        None
+    };
+    Ok((bytes, report))
+}
+
+pub fn decode(mut args: Args) -> Result<(), String> {
+    let format = args.option("format")?;
+    let [input, output] = args.finish_exact(["<input>", "<output>"])?;
+
+    let format = match format {
+        Some(format) => ImageFormat::from_extension(&format)
+            .ok_or_else(|| format!("unknown image format {}", format))?,
+        None if output == "-" => ImageFormat::Png,
+        None => ImageFormat::from_path(&output)
+            .map_err(|_| format!("can't tell the image format of {}, use --format", output))?,
+    };
+
+    let image = decode_qoi(&input)?;
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut bytes, format)
+        .map_err(|e| format!("failed to write {}: {}", output, e))?;
+
+    write_output(&output, &bytes)
+}
+
+//Unlike load_any this fails on anything other than a QOI file
+pub fn decode_qoi(path: &str) -> Result<DynamicImage, String> {
+    let bytes = read_input(path)?;
+    if !rust_qoi::is_qoi(&bytes) {
+        return Err(format!("{} isn't a QOI file", path));
+    }
+    rust_qoi::load_from_memory(&bytes).map_err(|e| format!("failed to decode {}: {}", path, e))
+}
+
+//QOI files are picked out by their magic bytes, everything else goes through image
+pub fn load_any(path: &str) -> Result<DynamicImage, String> {
+    let bytes = read_input(path)?;
+    rust_qoi::load_from_memory(&bytes).map_err(|e| format!("failed to read {}: {}", path, e))
+}
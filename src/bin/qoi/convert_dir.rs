@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use image::ImageFormat;
+use rust_qoi::encoder::Threads;
+use rust_qoi::header::ColorSpace;
+
+use crate::args::Args;
+use crate::convert::{colour_space_option, encode_image, parse_count, EncodeOptions};
+
+enum Outcome {
+    Converted { input_size: u64, output_size: u64 },
+    UpToDate,
+}
+
+pub fn convert_dir(mut args: Args) -> Result<(), String> {
+    let colour_space = colour_space_option(&mut args)?;
+    let jobs = match args.option("jobs")? {
+        Some(jobs) => parse_count(&jobs)?,
+        None => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+    };
+    let [input_dir, output_dir] = args.finish_exact(["<input dir>", "<output dir>"])?;
+    let (input_dir, output_dir) = (PathBuf::from(input_dir), PathBuf::from(output_dir));
+
+    let mut inputs = Vec::new();
+    find_images(&input_dir, &mut inputs)
+        .map_err(|e| format!("failed to read {}: {}", input_dir.display(), e))?;
+    inputs.sort();
+
+    //a.png and a.jpg would both become a.qoi, so neither is converted rather than picking one
+    let output_of = |input: &PathBuf| {
+        let relative = input.strip_prefix(&input_dir).unwrap();
+        output_dir.join(relative).with_extension("qoi")
+    };
+    let mut sources: HashMap<PathBuf, Vec<&PathBuf>> = HashMap::with_capacity(inputs.len());
+    for input in &inputs {
+        sources.entry(output_of(input)).or_default().push(input);
+    }
+
+    let mut files = Vec::with_capacity(inputs.len());
+    let mut clashes = 0;
+    for input in &inputs {
+        let output = output_of(input);
+        let others: Vec<String> = sources[&output]
+            .iter()
+            .filter(|&&other| other != input)
+            .map(|other| other.display().to_string())
+            .collect();
+        if others.is_empty() {
+            files.push((input, output));
+        } else {
+            eprintln!(
+                "qoi: failed to convert {}: {} also converts to {}",
+                input.display(),
+                others.join(", "),
+                output.display()
+            );
+            clashes += 1;
+        }
+    }
+
+    //Each thread takes the next file that nobody has started on
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::with_capacity(files.len()));
+    thread::scope(|s| {
+        for _ in 0..jobs.min(files.len()) {
+            s.spawn(|| {
+                while let Some((input, output)) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let outcome = convert_file(input, output, colour_space);
+
+                    if let Err(e) = &outcome {
+                        eprintln!("qoi: failed to convert {}: {}", input.display(), e);
+                    }
+                    outcomes.lock().unwrap().push(outcome);
+                }
+            });
+        }
+    });
+
+    let (mut converted, mut up_to_date, mut failed) = (0, 0, clashes);
+    let (mut input_size, mut output_size) = (0, 0);
+    for outcome in outcomes.into_inner().unwrap() {
+        match outcome {
+            Ok(Outcome::Converted {
+                input_size: i,
+                output_size: o,
+            }) => {
+                converted += 1;
+                input_size += i;
+                output_size += o;
+            }
+            Ok(Outcome::UpToDate) => up_to_date += 1,
+            Err(_) => failed += 1,
+        }
+    }
+
+    println!(
+        "converted {}, up to date {}, failed {}",
+        converted, up_to_date, failed
+    );
+    if converted > 0 {
+        println!(
+            "{} bytes -> {} bytes, {:.1}% of the original size",
+            input_size,
+            output_size,
+            output_size as f64 * 100.0 / input_size as f64
+        );
+    }
+
+    if failed > 0 {
+        return Err(format!("{} files failed to convert", failed));
+    }
+    Ok(())
+}
+
+//Collects every file image can read, QOI files are left alone. Symlinked directories aren't
+//followed, so a link back up the tree can't loop forever
+fn find_images(dir: &Path, images: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_images(&path, images)?;
+        } else if ImageFormat::from_path(&path).is_ok() {
+            images.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn convert_file(input: &Path, output: &Path, colour_space: ColorSpace) -> Result<Outcome, String> {
+    let input_metadata = fs::metadata(input).map_err(|e| e.to_string())?;
+    if let Ok(output_metadata) = fs::metadata(output) {
+        if let (Ok(input_time), Ok(output_time)) =
+            (input_metadata.modified(), output_metadata.modified())
+        {
+            if output_time > input_time {
+                return Ok(Outcome::UpToDate);
+            }
+        }
+    }
+
+    let image = image::open(input).map_err(|e| e.to_string())?;
+    //Files are already converted in parallel, so each one only gets a single thread
+    let options = EncodeOptions {
+        colour_space,
+        threads: Threads::Fixed(1),
+        ..EncodeOptions::default()
+    };
+    let (bytes, _) = encode_image(&image, &options).map_err(|e| e.to_string())?;
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    //Written alongside and then renamed over the output, so a conversion that's cut short never
+    //leaves a partial file that looks up to date next time
+    let temp = output.with_extension("qoi.tmp");
+    fs::write(&temp, &bytes)
+        .and_then(|_| fs::rename(&temp, output))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            e.to_string()
+        })?;
+
+    Ok(Outcome::Converted {
+        input_size: input_metadata.len(),
+        output_size: bytes.len() as u64,
+    })
+}
@@ -0,0 +1,33 @@
+use rust_qoi::diff::{diff as diff_images, diff_mask};
+
+use crate::args::Args;
+use crate::convert::load_any;
+
+pub fn diff(mut args: Args) -> Result<(), String> {
+    let mask_path = args.option("mask")?;
+    let [a_path, b_path] = args.finish_exact(["<a>", "<b>"])?;
+
+    let a = load_any(&a_path)?.to_rgba8();
+    let b = load_any(&b_path)?.to_rgba8();
+    let diff = diff_images(&a, &b).map_err(|e| e.to_string())?;
+
+    println!(
+        "differing pixels: {} of {}",
+        diff.differing_pixels,
+        a.width() as u64 * a.height() as u64
+    );
+    println!("max delta (RGBA): {:?}", diff.max_delta);
+    println!("PSNR:             {:.2} dB", diff.psnr);
+
+    if let Some(mask_path) = mask_path {
+        diff_mask(&a, &b)
+            .map_err(|e| e.to_string())?
+            .save_with_format(&mask_path, image::ImageFormat::Png)
+            .map_err(|e| format!("failed to write {}: {}", mask_path, e))?;
+    }
+
+    if !diff.is_identical() {
+        return Err("images differ".to_owned());
+    }
+    Ok(())
+}
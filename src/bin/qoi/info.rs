@@ -0,0 +1,108 @@
+use rust_qoi::header::ColorSpace;
+use rust_qoi::info::{heat_map, inspect};
+
+use crate::args::Args;
+use crate::read_input;
+
+pub fn info(mut args: Args) -> Result<(), String> {
+    let heat_map_path = args.option("heat-map")?;
+    let paths = args.finish()?;
+    if paths.is_empty() {
+        return Err("expected at least one file".to_owned());
+    }
+
+    if let Some(heat_map_path) = heat_map_path {
+        if paths.len() != 1 {
+            return Err("--heat-map only works with one file".to_owned());
+        }
+        let bytes = read_input(&paths[0])?;
+        heat_map(&bytes)
+            .map_err(|e| format!("failed to decode {}: {}", paths[0], e))?
+            .save_with_format(&heat_map_path, image::ImageFormat::Png)
+            .map_err(|e| format!("failed to write {}: {}", heat_map_path, e))?;
+    }
+
+    for path in paths {
+        let bytes = read_input(&path)?;
+        let info = inspect(&bytes).map_err(|e| format!("failed to decode {}: {}", path, e))?;
+        let header = info.header;
+
+        println!("{}", path);
+        println!("  dimensions:        {}x{}", header.width, header.height);
+        println!(
+            "  channels:          {} ({})",
+            header.channels,
+            if header.channels == 4 { "RGBA" } else { "RGB" }
+        );
+        println!(
+            "  colour space:      {}",
+            match header.color_space {
+                ColorSpace::Srgb => "sRGB",
+                ColorSpace::Linear => "linear",
+            }
+        );
+        println!("  file size:         {} bytes", info.file_size);
+        println!(
+            "  bits per pixel:    {}",
+            or_none(info.bits_per_pixel().map(|bits| format!("{:.2}", bits)))
+        );
+        println!("  compression ratio: {:.2}:1", info.compression_ratio());
+        println!(
+            "  end marker:        {}",
+            if info.end_marker_valid {
+                "valid"
+            } else {
+                "missing"
+            }
+        );
+
+        let ops = info.ops;
+        let total = ops.total();
+        println!("  ops:               {}", total);
+        for (name, count) in [
+            ("OP_RUN", ops.run),
+            ("OP_INDEX", ops.index),
+            ("OP_DIFF", ops.diff),
+            ("OP_LUMA", ops.luma),
+            ("OP_RGB", ops.rgb),
+            ("OP_RGBA", ops.rgba),
+        ] {
+            println!(
+                "    {:<9} {:>10} {:>6.2}%",
+                name,
+                count,
+                percent(count, total)
+            );
+        }
+        println!(
+            "  index hit rate:    {}",
+            or_none(
+                info.index_hit_rate()
+                    .map(|rate| format!("{:.2}%", rate * 100.0))
+            )
+        );
+
+        if ops.run > 0 {
+            println!("  run lengths:");
+            for (i, &count) in info.run_lengths.iter().enumerate() {
+                if count > 0 {
+                    println!("    {:>2} {:>10}", i + 1, count);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+//Stats which don't exist for the image, e.g. bits per pixel of an empty image
+fn or_none(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "n/a".to_owned())
+}
@@ -0,0 +1,92 @@
+mod args;
+mod bench;
+mod convert;
+mod convert_dir;
+mod diff;
+mod info;
+mod verify;
+
+use std::io::{Read, Write};
+use std::process;
+
+use args::Args;
+
+const USAGE: &str = "usage: qoi <command> [options]
+
+commands:
+    encode [--colorspace srgb|linear] [--threads auto|N] [--max-error N] [--colors N [--dither]]
+           <input> <output>
+        Converts any image the image crate can read into a QOI file
+        --max-error lets each channel be off by up to N for a smaller, lossy file
+        --colors reduces the image to a palette of up to N colours, optionally dithered
+        Lossy encodes print the file size and PSNR
+    decode [--format png|jpeg|bmp|...] <input> <output>
+        Converts a QOI file into another format, picked from --format or the output's extension
+    convert-dir [--colorspace srgb|linear] [--jobs N] <input dir> <output dir>
+        Converts every image under the input directory into QOI files under the output directory,
+        skipping outputs which are newer than their input
+    info [--heat-map <png>] <files...>
+        Prints a QOI file's header, compression and how often each op is used
+        --heat-map also saves a PNG where each pixel is coloured by the op that produced it
+    verify [--max N] <files...>
+        Checks QOI files against the spec, and lists up to N ops the reference encoder (qoi.h)
+        wouldn't have written
+    diff [--mask <png>] <a> <b>
+        Compares the pixels of two images, --mask saves a PNG which is white wherever they differ
+    bench [--iterations N] <images...>
+        Times encoding and decoding each image as QOI in every encoder mode, and as PNG
+
+Use - as a path to read from stdin or write to stdout.";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    let args = Args::new(args.collect());
+
+    let result = match command.as_deref() {
+        Some("encode") => convert::encode(args),
+        Some("decode") => convert::decode(args),
+        Some("bench") => bench::bench(args),
+        Some("convert-dir") => convert_dir::convert_dir(args),
+        Some("diff") => diff::diff(args),
+        Some("info") => info::info(args),
+        Some("verify") => verify::verify(args),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command {}\n\n{}", command, USAGE)),
+        None => Err(USAGE.to_owned()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("qoi: {}", e);
+        process::exit(1);
+    }
+}
+
+//- means stdin
+pub fn read_input(path: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    if path == "-" {
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("failed to read stdin: {}", e))?;
+    } else {
+        bytes = std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    }
+    Ok(bytes)
+}
+
+//- means stdout
+pub fn write_output(path: &str, bytes: &[u8]) -> Result<(), String> {
+    if path == "-" {
+        let mut stdout = std::io::stdout();
+        stdout
+            .write_all(bytes)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| format!("failed to write to stdout: {}", e))
+    } else {
+        std::fs::write(path, bytes).map_err(|e| format!("failed to write {}: {}", path, e))
+    }
+}
@@ -0,0 +1,68 @@
+use rust_qoi::verify::{verify as verify_file, Canonical};
+
+use crate::args::Args;
+use crate::read_input;
+
+pub fn verify(mut args: Args) -> Result<(), String> {
+    let max_suboptimal = match args.option("max")? {
+        Some(max) => max
+            .parse()
+            .map_err(|_| format!("invalid value {} for --max", max))?,
+        None => 20,
+    };
+    let paths = args.finish()?;
+    if paths.is_empty() {
+        return Err("expected at least one file".to_owned());
+    }
+
+    let mut invalid = 0;
+    for path in &paths {
+        let bytes = read_input(path)?;
+        let report = verify_file(&bytes);
+
+        for e in &report.errors {
+            println!("{}: error: {}", path, e);
+        }
+        if report.trailing_data > 0 {
+            println!(
+                "{}: error: {} bytes after the end marker",
+                path, report.trailing_data
+            );
+        }
+
+        for suboptimal in report.suboptimal.iter().take(max_suboptimal) {
+            let canonical = match suboptimal.canonical {
+                Canonical::Op(op) => format!("{} would apply", op.name()),
+                Canonical::ExtendRun => "the previous run could be extended".to_owned(),
+            };
+            println!(
+                "{}: byte {}, pixel {}: {} where {}",
+                path,
+                suboptimal.offset,
+                suboptimal.pixel,
+                suboptimal.found.name(),
+                canonical
+            );
+        }
+        if report.suboptimal.len() > max_suboptimal {
+            println!(
+                "{}: {} more suboptimal ops",
+                path,
+                report.suboptimal.len() - max_suboptimal
+            );
+        }
+
+        if report.is_valid() {
+            if report.suboptimal.is_empty() {
+                println!("{}: ok", path);
+            }
+        } else {
+            invalid += 1;
+        }
+    }
+
+    if invalid > 0 {
+        return Err(format!("{} of {} files are invalid", invalid, paths.len()));
+    }
+    Ok(())
+}
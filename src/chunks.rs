@@ -1,4 +1,4 @@
-#![allow(non_camel_case_types)]
+#![allow(non_camel_case_types, clippy::upper_case_acronyms)]
 
 use std::fmt::Debug;
 use std::io::{Bytes, Read, Write};
@@ -7,8 +7,11 @@ use std::num::Wrapping;
 
 use crate::codec::QoiCodecState;
 use crate::consts::SEEN_PIXEL_ARRAY_SIZE;
+use crate::error::QoiError;
 use crate::util::Pixel;
 
+const MAX_CHUNK_SIZE: usize = OP_RGBA::SIZE;
+
 pub(crate) enum QoiChunk {
     RGB(OP_RGB),
     RGBA(OP_RGBA),
@@ -33,22 +36,58 @@ impl QoiChunk {
     pub(crate) fn decode<R: Read>(
         buf: &mut Peekable<Bytes<R>>,
         state: &QoiCodecState,
-    ) -> Self {
-        if let Some(chunk) = OP_DIFF::try_decode(buf) {
-            QoiChunk::DIFF(chunk)
-        } else if let Some(chunk) = OP_INDEX::try_decode(buf) {
-            QoiChunk::INDEX(chunk)
-        } else if let Some(chunk) = OP_LUMA::try_decode(buf) {
-            QoiChunk::LUMA(chunk)
-        } else if let Some(chunk) = OP_RGBA::try_decode(buf) {
-            QoiChunk::RGBA(chunk)
-        } else if let Some(mut chunk) = OP_RGB::try_decode(buf) {
+    ) -> Result<Self, QoiError> {
+        let flag = match buf.peek() {
+            Some(Ok(flag)) => *flag,
+            Some(Err(_)) => return Err(buf.next().unwrap().unwrap_err().into()),
+            None => return Err(QoiError::TruncatedData),
+        };
+
+        let mut bytes = [0u8; MAX_CHUNK_SIZE];
+        let bytes = &mut bytes[..Self::size(flag)];
+        for byte in bytes.iter_mut() {
+            *byte = buf.next().ok_or(QoiError::TruncatedData)??;
+        }
+
+        Self::decode_slice(bytes, state).map(|(chunk, _)| chunk)
+    }
+
+    //Returns the chunk at the start of buf, and how many bytes it took up
+    pub(crate) fn decode_slice(
+        buf: &[u8],
+        state: &QoiCodecState,
+    ) -> Result<(Self, usize), QoiError> {
+        let flag = *buf.first().ok_or(QoiError::TruncatedData)?;
+
+        //Every flag matches one of these, the 8 bit flags have to be checked before OP_RUN
+        let chunk = if OP_DIFF::matches(flag) {
+            (QoiChunk::DIFF(OP_DIFF::decode(buf)?), OP_DIFF::SIZE)
+        } else if OP_INDEX::matches(flag) {
+            (QoiChunk::INDEX(OP_INDEX::decode(buf)?), OP_INDEX::SIZE)
+        } else if OP_LUMA::matches(flag) {
+            (QoiChunk::LUMA(OP_LUMA::decode(buf)?), OP_LUMA::SIZE)
+        } else if OP_RGBA::matches(flag) {
+            (QoiChunk::RGBA(OP_RGBA::decode(buf)?), OP_RGBA::SIZE)
+        } else if OP_RGB::matches(flag) {
+            let mut chunk = OP_RGB::decode(buf)?;
             chunk.a = state.last_pixel().a();
-            QoiChunk::RGB(chunk)
-        } else if let Some(chunk) = OP_RUN::try_decode(buf) {
-            QoiChunk::RUN(chunk)
+            (QoiChunk::RGB(chunk), OP_RGB::SIZE)
         } else {
-            unreachable!()
+            (QoiChunk::RUN(OP_RUN::decode(buf)?), OP_RUN::SIZE)
+        };
+        Ok(chunk)
+    }
+
+    //The size of the chunk starting with flag
+    pub(crate) fn size(flag: u8) -> usize {
+        if OP_RGBA::matches(flag) {
+            OP_RGBA::SIZE
+        } else if OP_RGB::matches(flag) {
+            OP_RGB::SIZE
+        } else if OP_LUMA::matches(flag) {
+            OP_LUMA::SIZE
+        } else {
+            1
         }
     }
 }
@@ -65,31 +104,19 @@ where
         writer.write_all(&bytes)
     }
 
-    fn try_decode<R: Read>(buf: &mut Peekable<Bytes<R>>) -> Option<Self>
+    //Assumes the flag has already been matched
+    fn decode(buf: &[u8]) -> Result<Self, QoiError>
     where
         Self: Sized,
     {
-        let flag = *buf.peek()?.as_ref().ok()?;
-        if Self::matches(flag) {
-            let bytes = buf.take(N).collect::<Vec<std::io::Result<u8>>>();
-            if bytes.len() != N {
-                return None;
-            }
-
-            let bytes = bytes
-                .iter()
-                .map(|r| *r.as_ref().unwrap())
-                .collect::<Vec<u8>>();
+        let bytes = buf.get(..N).ok_or(QoiError::TruncatedData)?;
 
-            let chunk = Self::from_bytes(&bytes);
+        let chunk = Self::from_bytes(bytes);
 
-            #[cfg(test)]
-            println!("{:?}", chunk);
+        #[cfg(test)]
+        println!("{:?}", chunk);
 
-            Some(chunk)
-        } else {
-            None
-        }
+        Ok(chunk)
     }
 
     fn matches(byte: u8) -> bool;
@@ -100,9 +127,9 @@ where
 
 #[derive(Debug)]
 pub(crate) struct OP_RGB {
-    r: u8,
-    g: u8,
-    b: u8,
+    pub(crate) r: u8,
+    pub(crate) g: u8,
+    pub(crate) b: u8,
     a: u8, //This field isn't encoded, but the information comes in handy during encoding
 }
 
@@ -153,10 +180,10 @@ impl QOI_CHUNK<{ Self::SIZE }> for OP_RGB {
 
 #[derive(Debug)]
 pub(crate) struct OP_RGBA {
-    r: u8,
-    g: u8,
-    b: u8,
-    a: u8,
+    pub(crate) r: u8,
+    pub(crate) g: u8,
+    pub(crate) b: u8,
+    pub(crate) a: u8,
 }
 
 impl OP_RGBA {
@@ -206,7 +233,7 @@ impl QOI_CHUNK<{ Self::SIZE }> for OP_RGBA {
 
 #[derive(Debug)]
 pub(crate) struct OP_INDEX {
-    index: u8,
+    pub(crate) index: u8,
 }
 
 impl OP_INDEX {
@@ -242,9 +269,9 @@ impl QOI_CHUNK<{ Self::SIZE }> for OP_INDEX {
 
 #[derive(Debug)]
 pub(crate) struct OP_DIFF {
-    dr: u8,
-    dg: u8,
-    db: u8,
+    pub(crate) dr: u8,
+    pub(crate) dg: u8,
+    pub(crate) db: u8,
 }
 
 impl OP_DIFF {
@@ -298,9 +325,9 @@ impl QOI_CHUNK<{ Self::SIZE }> for OP_DIFF {
 
 #[derive(Debug)]
 pub(crate) struct OP_LUMA {
-    dg: u8,
-    dr_dg: u8,
-    db_dg: u8,
+    pub(crate) dg: u8,
+    pub(crate) dr_dg: u8,
+    pub(crate) db_dg: u8,
 }
 
 impl OP_LUMA {
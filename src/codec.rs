@@ -1,4 +1,5 @@
 use std::convert::TryInto;
+use std::ops::RangeInclusive;
 
 use crate::chunks::{QoiChunk, OP_DIFF, OP_INDEX, OP_LUMA, OP_RGB, OP_RGBA, OP_RUN};
 use crate::consts::*;
@@ -10,25 +11,41 @@ pub(crate) struct QoiCodecState {
     previously_seen: [Pixel; SEEN_PIXEL_ARRAY_SIZE],
     run_length: u8,
     modified: u64, //This must be the same as SEEN_PIXEL_ARRAY_SIZE
+    max_error: u8,
 }
 
 impl QoiCodecState {
     pub(crate) fn new() -> Self {
+        Self::starting_from(Pixel::new(0, 0, 0, 255))
+    }
+
+    //Used to encode a segment of an image on its own, the seen pixel array is left unmodified so
+    //that the segment can be stitched back on top of the state of the segments before it
+    pub(crate) fn starting_from(last_pixel: Pixel) -> Self {
         Self {
-            last_pixel: Pixel::new(0, 0, 0, 255),
+            last_pixel,
             previously_seen: [Pixel::new(0, 0, 0, 0); SEEN_PIXEL_ARRAY_SIZE],
             run_length: 0,
             modified: 0,
+            max_error: 0,
         }
     }
 
-    pub(crate) fn process_pixel<const CHANNELS: u8>(
-        &mut self,
-        pixel: Pixel,
-    ) -> Vec<ChunkState> {
+    //Lets process_pixel swap a pixel for any pixel within max_error of it on every channel
+    pub(crate) fn with_max_error(mut self, max_error: u8) -> Self {
+        self.max_error = max_error;
+        self
+    }
+
+    pub(crate) fn process_pixel<const CHANNELS: u8>(&mut self, pixel: Pixel) -> PixelChunks {
         let is_rgb = CHANNELS == RGB_CHANNELS;
+        let pixel = if self.max_error > 0 {
+            self.approximate(pixel)
+        } else {
+            pixel
+        };
 
-        let mut chunks = vec![];
+        let mut chunks = PixelChunks::default();
         let hash_idx = pixel.hash();
 
         //1. Pixel == last pixel -> run length
@@ -36,7 +53,7 @@ impl QoiCodecState {
             self.run_length += 1;
             return self.cleanup(chunks, None, pixel);
         } else if self.run_length > 0 {
-            chunks.push(ChunkState::Resolved(QoiChunk::RUN(OP_RUN::new(self.run_length))));
+            chunks.run = Some(QoiChunk::RUN(OP_RUN::new(self.run_length)));
 
             if pixel == self.last_pixel {
                 self.run_length = 1;
@@ -47,8 +64,10 @@ impl QoiCodecState {
         }
 
         //2. Pixel seen before -> index
+        //If the slot hasn't been modified yet the chunk is left unresolved, and the index is checked
+        //again against the state this one gets merged into
         let looked_up_pixel = self.previously_seen[hash_idx];
-        if looked_up_pixel == pixel {
+        if self.modified(hash_idx) && looked_up_pixel == pixel {
             return self.cleanup(
                 chunks,
                 Some(QoiChunk::INDEX(OP_INDEX::new(hash_idx as u8))),
@@ -75,21 +94,73 @@ impl QoiCodecState {
         self.cleanup(chunks, Some(chunk), pixel)
     }
 
+    //Picks the pixel within max_error of pixel that the smallest chunk can reach. The decoder only
+    //ever sees the pixel that's picked, so it becomes last_pixel and errors don't build up
+    fn approximate(&self, pixel: Pixel) -> Pixel {
+        let last = self.last_pixel;
+        let close = |candidate: Pixel| distance(pixel, candidate) <= self.max_error;
+
+        if close(last) {
+            return last;
+        }
+
+        //Any slot can be indexed, not just the one the pixel hashes to
+        let indexed = (0..SEEN_PIXEL_ARRAY_SIZE)
+            .filter(|&i| self.modified(i))
+            .map(|i| self.previously_seen[i])
+            .filter(|&seen| close(seen))
+            .min_by_key(|&seen| distance(pixel, seen));
+        if let Some(seen) = indexed {
+            return seen;
+        }
+
+        //Diffs and lumas keep the alpha, so it has to be close enough already
+        if pixel.a().abs_diff(last.a()) > self.max_error {
+            return pixel;
+        }
+
+        let diff = Pixel::new(
+            step(last.r(), pixel.r(), 0, -2..=1),
+            step(last.g(), pixel.g(), 0, -2..=1),
+            step(last.b(), pixel.b(), 0, -2..=1),
+            last.a(),
+        );
+        if close(diff) {
+            return diff;
+        }
+
+        //Red and blue move with green, then by up to 8 more
+        let g = step(last.g(), pixel.g(), 0, -32..=31);
+        let dg = g.wrapping_sub(last.g()) as i8;
+        let luma = Pixel::new(
+            step(last.r(), pixel.r(), dg, -8..=7),
+            g,
+            step(last.b(), pixel.b(), dg, -8..=7),
+            last.a(),
+        );
+        if close(luma) {
+            return luma;
+        }
+
+        //Keeping the alpha still saves a byte over OP_RGBA
+        Pixel::new(pixel.r(), pixel.g(), pixel.b(), last.a())
+    }
+
     //This only exists because every time we need to return something from process_pixel, there's some cleanup code that needs to be run
     #[inline]
     fn cleanup(
         &mut self,
-        mut chunks: Vec<ChunkState>,
+        mut chunks: PixelChunks,
         chunk: Option<QoiChunk>,
         pixel: Pixel,
-    ) -> Vec<ChunkState> {
-        if let Some(chunk) = chunk {
-            chunks.push(if self.is_resolved(&chunk, &pixel) {
+    ) -> PixelChunks {
+        chunks.chunk = chunk.map(|chunk| {
+            if self.is_resolved(&chunk, &pixel) {
                 ChunkState::Resolved(chunk)
             } else {
-                ChunkState::Unresolved(chunk, pixel.clone())
-            });
-        }
+                ChunkState::Unresolved(chunk, pixel)
+            }
+        });
         let hash_idx = pixel.hash();
         self.previously_seen[hash_idx] = pixel;
         self.modified |= 1 << hash_idx;
@@ -107,8 +178,16 @@ impl QoiCodecState {
 
     //Will optionally return a run length encoded chunk
     pub(crate) fn drain(&mut self) -> Option<(QoiChunk, bool)> {
-        if self.run_length > 1 {
-            Some((QoiChunk::RUN(OP_RUN::new(self.run_length)), true))
+        self.flush_run().map(|chunk| (chunk, true))
+    }
+
+    //Ends the current run early, this is needed when a segment encoded on its own starts right
+    //after the run
+    pub(crate) fn flush_run(&mut self) -> Option<QoiChunk> {
+        if self.run_length > 0 {
+            let run = QoiChunk::RUN(OP_RUN::new(self.run_length));
+            self.run_length = 0;
+            Some(run)
         } else {
             None
         }
@@ -135,13 +214,9 @@ impl QoiCodecState {
         self.last_pixel
     }
 
-    pub(crate) fn run_length(&self) -> u8 {
-        self.run_length
-    }
-
     pub(crate) fn lookup_pixel(&self, pixel: &Pixel) -> Option<QoiChunk> {
         let hash_idx = pixel.hash();
-        if self.modified(hash_idx) && self.previously_seen[hash_idx] == *pixel {
+        if self.previously_seen[hash_idx] == *pixel {
             Some(QoiChunk::INDEX(OP_INDEX::new(hash_idx.try_into().unwrap())))
         } else {
             None
@@ -149,6 +224,27 @@ impl QoiCodecState {
     }
 }
 
+//The largest difference between two pixels on any channel
+fn distance(a: Pixel, b: Pixel) -> u8 {
+    [
+        a.r().abs_diff(b.r()),
+        a.g().abs_diff(b.g()),
+        a.b().abs_diff(b.b()),
+        a.a().abs_diff(b.a()),
+    ]
+    .iter()
+    .copied()
+    .max()
+    .unwrap()
+}
+
+//Moves from towards to by offset plus as much of the rest of the way as range allows, wrapping
+//like the decoder does
+fn step(from: u8, to: u8, offset: i8, range: RangeInclusive<i16>) -> u8 {
+    let rest = (to as i16 - from as i16 - offset as i16).clamp(*range.start(), *range.end());
+    from.wrapping_add(offset as u8).wrapping_add(rest as u8)
+}
+
 //This covers all methods related to decoding
 impl QoiCodecState {
     pub(crate) fn lookup_chunk(&self, chunk: QoiChunk) -> Pixel {
@@ -163,13 +259,33 @@ impl QoiCodecState {
     }
 
     pub(crate) fn process_chunk(&mut self, chunk: QoiChunk) -> (Pixel, usize) {
-        if let QoiChunk::RUN(chunk) = chunk {
-            (self.last_pixel, chunk.run_length() as usize)
+        //Runs still store the pixel, the encoder does the same so it can index the starting pixel
+        let repeats = if let QoiChunk::RUN(chunk) = chunk {
+            chunk.run_length() as usize
         } else {
             self.last_pixel = self.lookup_chunk(chunk);
-            self.previously_seen[self.last_pixel.hash()] = self.last_pixel;
-            (self.last_pixel, 1)
-        }
+            1
+        };
+        self.previously_seen[self.last_pixel.hash()] = self.last_pixel;
+        (self.last_pixel, repeats)
+    }
+}
+
+//A pixel ends up as at most a run followed by one other chunk
+#[derive(Default)]
+pub(crate) struct PixelChunks {
+    run: Option<QoiChunk>,
+    chunk: Option<ChunkState>,
+}
+
+impl Iterator for PixelChunks {
+    type Item = ChunkState;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.run
+            .take()
+            .map(ChunkState::Resolved)
+            .or_else(|| self.chunk.take())
     }
 }
 
@@ -180,10 +296,11 @@ pub(crate) enum ChunkState {
 }
 
 impl ChunkState {
-    pub(crate) fn get_chunk(self) -> QoiChunk {
+    //Unresolved chunks become an index if the state they're merged into has already seen the pixel
+    pub(crate) fn resolve(self, state: &QoiCodecState) -> QoiChunk {
         match self {
             ChunkState::Resolved(chunk) => chunk,
-            ChunkState::Unresolved(chunk, _) => chunk,
+            ChunkState::Unresolved(chunk, pixel) => state.lookup_pixel(&pixel).unwrap_or(chunk),
         }
     }
 }
\ No newline at end of file
@@ -2,3 +2,4 @@ pub const RGB_CHANNELS: u8 = 3;
 pub const RGBA_CHANNELS: u8 = 4;
 pub const SEEN_PIXEL_ARRAY_SIZE: usize = 64;
 pub const MAX_RUN_LENGTH: u8 = 62;
+pub const END_MARKER: [u8; 8] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
@@ -0,0 +1,81 @@
+use image::ColorType;
+
+use crate::error::QoiError;
+
+/// How 16 bit channels are reduced to the 8 bits QOI stores.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Downsample {
+    /// Round every channel to the nearest 8 bit value.
+    #[default]
+    Round,
+    /// Spread the rounding error out with an ordered dither, which avoids banding in smooth
+    /// gradients. Alpha is always rounded.
+    Dither,
+}
+
+//4x4 Bayer matrix
+const DITHER_THRESHOLDS: [[u32; 4]; 4] =
+    [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl Downsample {
+    fn apply(self, value: u16, x: usize, y: usize, is_alpha: bool) -> u8 {
+        let offset = match self {
+            Downsample::Dither if !is_alpha => {
+                (DITHER_THRESHOLDS[y % 4][x % 4] * 2 + 1) * u16::MAX as u32 / 32
+            }
+            _ => u16::MAX as u32 / 2,
+        };
+        ((value as u32 * 255 + offset) / u16::MAX as u32) as u8
+    }
+}
+
+//Converts any colour type into packed RGB or RGBA, returning the pixels and their channel count
+pub(crate) fn to_rgb_or_rgba(
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    downsample: Downsample,
+) -> Result<Option<(Vec<u8>, u8)>, QoiError> {
+    //Which input channel each output channel comes from
+    let (layout, bytes_per_channel): (&[usize], usize) = match color_type {
+        ColorType::L8 => (&[0, 0, 0], 1),
+        ColorType::La8 => (&[0, 0, 0, 1], 1),
+        ColorType::Rgb8 => (&[0, 1, 2], 1),
+        ColorType::Rgba8 => (&[0, 1, 2, 3], 1),
+        ColorType::Bgr8 => (&[2, 1, 0], 1),
+        ColorType::Bgra8 => (&[2, 1, 0, 3], 1),
+        ColorType::L16 => (&[0, 0, 0], 2),
+        ColorType::La16 => (&[0, 0, 0, 1], 2),
+        ColorType::Rgb16 => (&[0, 1, 2], 2),
+        ColorType::Rgba16 => (&[0, 1, 2, 3], 2),
+        _ => return Ok(None),
+    };
+
+    let bytes_per_pixel = color_type.bytes_per_pixel() as usize;
+    let expected = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+        .ok_or(QoiError::DimensionOverflow { width, height })?;
+    if buf.len() != expected {
+        return Err(QoiError::BufferSizeMismatch {
+            expected,
+            actual: buf.len(),
+        });
+    }
+
+    let mut pixels = Vec::with_capacity(buf.len() / bytes_per_pixel * layout.len());
+    for (i, pixel) in buf.chunks_exact(bytes_per_pixel).enumerate() {
+        let (x, y) = (i % width as usize, i / width as usize);
+        for (channel, &source) in layout.iter().enumerate() {
+            pixels.push(if bytes_per_channel == 1 {
+                pixel[source]
+            } else {
+                //16 bit buffers from image hold native endian u16s
+                let value = u16::from_ne_bytes([pixel[source * 2], pixel[source * 2 + 1]]);
+                downsample.apply(value, x, y, channel == 3)
+            });
+        }
+    }
+    Ok(Some((pixels, layout.len() as u8)))
+}
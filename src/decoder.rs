@@ -1,86 +1,312 @@
 use std::{
-    convert::TryInto,
-    error,
-    fmt::Display,
     io::{Bytes, Read},
     iter::Peekable,
+    ops::Range,
 };
 
-use image::{error::DecodingError, ImageDecoder, ImageError, ImageResult};
+use image::error::{ImageError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::{ImageDecoder, ImageResult};
 
 use crate::{
     chunks::QoiChunk,
     codec::QoiCodecState,
+    consts::{END_MARKER, RGBA_CHANNELS, RGB_CHANNELS},
+    error::QoiError,
+    header::{ColorSpace, Header, HEADER_SIZE},
+    util::{strided_len, Pixel},
 };
 
-const HEADER_SIZE: usize = 14;
+/// Upper bounds on the images `QoiDecoder` accepts, checked against the header before anything is
+/// decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64,
+    /// The maximum number of bytes the decoded image can take up.
+    pub max_alloc: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: u64::MAX,
+            max_alloc: u64::MAX,
+        }
+    }
+}
 
-#[derive(Debug)]
-enum DecoderError {
-    HeaderTooSmall,
-    InvalidHeader,
+impl Limits {
+    fn check(&self, header: &Header) -> Result<(), QoiError> {
+        let (width, height) = (header.width, header.height);
+
+        if width > self.max_width
+            || height > self.max_height
+            || header.pixels() > self.max_pixels
+            || header.image_size()? as u64 > self.max_alloc
+        {
+            return Err(QoiError::LimitsExceeded { width, height });
+        }
+        Ok(())
+    }
 }
 
-impl error::Error for DecoderError {}
+/// How the decoder treats a missing or garbled end marker.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EndMarkerMode {
+    /// Fail with `QoiError::MissingEndMarker`.
+    #[default]
+    Strict,
+    /// Carry on, and report it through `Footer::end_marker_valid`.
+    Lenient,
+}
+
+/// The order and number of channels `QoiDecoder` writes each pixel as. `decode_from_slice`,
+/// `decode_into` and `QoiIncrementalDecoder` always write the layout in the header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelLayout {
+    /// RGB or RGBA, matching the channels in the header.
+    #[default]
+    Native,
+    /// Drops the alpha channel of RGBA images.
+    Rgb,
+    /// RGB images are given an alpha of 255, as are all of the layouts below.
+    Rgba,
+    Bgra,
+    /// image has no ARGB colour type, so this only works with `read_image_with_footer`,
+    /// `read_rows` and `read_image_strided`. `ImageDecoder::into_reader` rejects it.
+    Argb,
+    /// Grayscale, using the same weights as image.
+    Luma,
+    LumaAlpha,
+}
+
+impl PixelLayout {
+    #[must_use]
+    pub fn bytes_per_pixel(self, header: &Header) -> usize {
+        match self {
+            PixelLayout::Native => header.channels as usize,
+            PixelLayout::Rgb => 3,
+            PixelLayout::Rgba | PixelLayout::Bgra | PixelLayout::Argb => 4,
+            PixelLayout::Luma => 1,
+            PixelLayout::LumaAlpha => 2,
+        }
+    }
+
+    fn color_type(self, header: &Header) -> image::ColorType {
+        match self {
+            PixelLayout::Native => header.color_type(),
+            PixelLayout::Rgb => image::ColorType::Rgb8,
+            PixelLayout::Rgba | PixelLayout::Argb => image::ColorType::Rgba8,
+            PixelLayout::Bgra => image::ColorType::Bgra8,
+            PixelLayout::Luma => image::ColorType::L8,
+            PixelLayout::LumaAlpha => image::ColorType::La8,
+        }
+    }
+
+    #[inline]
+    fn write(self, pixel: Pixel, channels: u8, buf: &mut [u8]) {
+        let (r, g, b) = (pixel.r(), pixel.g(), pixel.b());
+        let a = if channels == RGB_CHANNELS {
+            255
+        } else {
+            pixel.a()
+        };
+        let luma = || ((2126 * r as u32 + 7152 * g as u32 + 722 * b as u32) / 10000) as u8;
 
-impl Display for DecoderError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            DecoderError::HeaderTooSmall => write!(f, "Header too small"),
-            DecoderError::InvalidHeader => write!(f, "Invalid header"),
+            PixelLayout::Native => pixel.write_to(buf),
+            PixelLayout::Rgb => buf.copy_from_slice(&[r, g, b]),
+            PixelLayout::Rgba => buf.copy_from_slice(&[r, g, b, a]),
+            PixelLayout::Bgra => buf.copy_from_slice(&[b, g, r, a]),
+            PixelLayout::Argb => buf.copy_from_slice(&[a, r, g, b]),
+            PixelLayout::Luma => buf[0] = luma(),
+            PixelLayout::LumaAlpha => buf.copy_from_slice(&[luma(), a]),
         }
     }
 }
 
-impl From<DecoderError> for ImageError {
-    fn from(e: DecoderError) -> ImageError {
-        ImageError::Decoding(DecodingError::new(
-            image::error::ImageFormatHint::Name("QOI".to_string()),
-            e,
-        ))
+/// Everything after the last pixel of an image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Footer {
+    pub end_marker_valid: bool,
+    /// Any bytes after the end marker.
+    pub trailing_data: Vec<u8>,
+}
+
+/// Decodes a whole QOI file held in memory, returning its header and pixels.
+pub fn decode_from_slice(data: &[u8]) -> Result<(Header, Vec<u8>), QoiError> {
+    let header = Header::from_bytes(data)?;
+
+    header.check_plausible(data.len())?;
+
+    let mut buf = vec![0u8; header.image_size()?];
+    decode_into(data, &mut buf)?;
+    Ok((header, buf))
+}
+
+/// Decodes a whole QOI file held in memory into buf, which has to be exactly the size of the image.
+pub fn decode_into(data: &[u8], buf: &mut [u8]) -> Result<Header, QoiError> {
+    let header = Header::from_bytes(data)?;
+    let expected = header.image_size()?;
+    if buf.len() != expected {
+        return Err(QoiError::BufferSizeMismatch {
+            expected,
+            actual: buf.len(),
+        });
     }
+
+    let channels = header.channels as usize;
+    let mut state = QoiCodecState::new();
+    let mut pos = HEADER_SIZE;
+    let mut written = 0;
+
+    while written < buf.len() {
+        let (chunk, size) = QoiChunk::decode_slice(&data[pos..], &state)?;
+        pos += size;
+
+        //A run can't go past the end of the image
+        let (pixel, repeats) = state.process_chunk(chunk);
+        let end = buf.len().min(written + repeats * channels);
+        for px in buf[written..end].chunks_exact_mut(channels) {
+            pixel.write_to(px);
+        }
+        written = end;
+    }
+
+    if data.get(pos..(pos + END_MARKER.len())) != Some(&END_MARKER[..]) {
+        return Err(QoiError::MissingEndMarker);
+    }
+
+    Ok(header)
 }
 
 pub struct QoiDecoder<R: Read> {
     reader: R,
-    width: u32,
-    height: u32,
-    colour_type: image::ColorType,
-    //TODO: Understand what a colour space is
+    header: Header,
+    end_marker_mode: EndMarkerMode,
+    layout: PixelLayout,
 }
 
 impl<R: Read> QoiDecoder<R> {
-    pub fn new(reader: R) -> ImageResult<QoiDecoder<R>> {
-        let mut decoder = QoiDecoder {
+    pub fn new(reader: R) -> Result<QoiDecoder<R>, QoiError> {
+        Self::with_limits(reader, Limits::default())
+    }
+
+    pub fn with_limits(mut reader: R, limits: Limits) -> Result<QoiDecoder<R>, QoiError> {
+        let header = Self::read_metadata(&mut reader, limits)?;
+        Ok(QoiDecoder {
             reader,
-            width: 0,
-            height: 0,
-            colour_type: image::ColorType::Rgba8,
-        };
-        decoder.read_metadata()?;
-        Ok(decoder)
+            header,
+            end_marker_mode: EndMarkerMode::default(),
+            layout: PixelLayout::default(),
+        })
     }
 
-    fn read_metadata(&mut self) -> ImageResult<()> {
-        let mut buf = [0u8; HEADER_SIZE];
-        self.reader
-            .read(&mut buf)
-            .or(Err(DecoderError::HeaderTooSmall))?;
+    pub fn with_end_marker_mode(mut self, end_marker_mode: EndMarkerMode) -> QoiDecoder<R> {
+        self.end_marker_mode = end_marker_mode;
+        self
+    }
+
+    /// Sets the layout pixels are written in, by default this matches the header.
+    pub fn with_layout(mut self, layout: PixelLayout) -> QoiDecoder<R> {
+        self.layout = layout;
+        self
+    }
 
-        if &buf[0..4] != b"qoif" {
-            return Err(DecoderError::InvalidHeader)?;
+    /// Decodes the image into buf, and then reads the rest of the file.
+    pub fn read_image_with_footer(self, buf: &mut [u8]) -> Result<Footer, QoiError> {
+        let total_bytes = self.total_bytes();
+        if buf.len() as u64 != total_bytes {
+            return Err(QoiError::BufferSizeMismatch {
+                expected: total_bytes as usize,
+                actual: buf.len(),
+            });
         }
 
-        self.width = u32::from_be_bytes(buf[4..8].try_into().unwrap());
-        self.height = u32::from_be_bytes(buf[8..12].try_into().unwrap());
+        let mut reader = self.reader();
+        reader.read_exact(buf)?;
+        reader.finish()
+    }
 
-        self.colour_type = match buf[12] {
-            3 => image::ColorType::Rgb8,
-            4 => image::ColorType::Rgba8,
-            _ => return Err(DecoderError::InvalidHeader)?,
-        };
+    /// Decodes the image one row at a time into row, calling f with the index of each row as soon
+    /// as it's complete.
+    pub fn read_rows<F>(self, row: &mut [u8], mut f: F) -> Result<Footer, QoiError>
+    where
+        F: FnMut(u32, &[u8]),
+    {
+        let (width, height) = (self.header.width, self.header.height);
+        let row_size = (width as usize)
+            .checked_mul(self.layout.bytes_per_pixel(&self.header))
+            .ok_or(QoiError::DimensionOverflow { width, height })?;
+        if row.len() != row_size {
+            return Err(QoiError::BufferSizeMismatch {
+                expected: row_size,
+                actual: row.len(),
+            });
+        }
 
-        Ok(())
+        let mut reader = self.reader();
+        for y in 0..height {
+            reader.read_row(row)?;
+            f(y, row);
+        }
+        reader.finish()
+    }
+
+    /// Decodes the image into a buffer where each row starts stride bytes after the last. To
+    /// decode into part of a larger image, pass the buffer starting at the top left pixel.
+    pub fn read_image_strided(self, buf: &mut [u8], stride: usize) -> Result<Footer, QoiError> {
+        let (width, height) = (self.header.width, self.header.height);
+        let row_size = (width as usize)
+            .checked_mul(self.layout.bytes_per_pixel(&self.header))
+            .ok_or(QoiError::DimensionOverflow { width, height })?;
+        let expected = strided_len(row_size, height, stride)?;
+        if buf.len() < expected {
+            return Err(QoiError::BufferSizeMismatch {
+                expected,
+                actual: buf.len(),
+            });
+        }
+
+        let mut reader = self.reader();
+        //Padding between rows is left untouched
+        for y in 0..height as usize {
+            reader.read_row(&mut buf[y * stride..][..row_size])?;
+        }
+        reader.finish()
+    }
+
+    fn reader(self) -> QoiReader<R> {
+        QoiReader {
+            layout: self.layout,
+            bytes_per_pixel: self.layout.bytes_per_pixel(&self.header),
+            end_marker_mode: self.end_marker_mode,
+            ..QoiReader::new(self.reader, self.header.channels, self.header.pixels())
+        }
+    }
+
+    /// Get the colour space from the image's header.
+    #[must_use]
+    pub fn color_space(&self) -> ColorSpace {
+        self.header.color_space
+    }
+
+    /// Get the image's header.
+    #[must_use]
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    fn read_metadata(reader: &mut R, limits: Limits) -> Result<Header, QoiError> {
+        let mut buf = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut buf)?;
+
+        let header = Header::from_bytes(&buf)?;
+        limits.check(&header)?;
+        Ok(header)
     }
 }
 
@@ -88,44 +314,153 @@ pub struct QoiReader<R: Read> {
     reader: Peekable<Bytes<R>>,
     state: QoiCodecState,
     channels: u8,
+    layout: PixelLayout,
+    bytes_per_pixel: usize,
+    pixels_remaining: u64,
+    pending_pixel: Pixel,
+    pending_repeats: usize, //Pixels of the last chunk which didn't fit in the caller's buffer
+    leftover: [u8; RGBA_CHANNELS as usize],
+    leftover_range: Range<usize>, //Bytes of leftover which didn't fit in the caller's buffer
+    end_marker_mode: EndMarkerMode,
+    end_marker_valid: Option<bool>,
 }
 
 impl<R: Read> QoiReader<R> {
-    pub fn new(reader: R, channels: u8) -> QoiReader<R> {
+    /// Reads pixels from the chunks which follow a header, stopping after pixels pixels and then
+    /// checking the end marker. `QoiDecoder::into_reader` reads the header for you.
+    pub fn new(reader: R, channels: u8, pixels: u64) -> QoiReader<R> {
         QoiReader {
+            #[allow(clippy::unbuffered_bytes)]
             reader: reader.bytes().peekable(),
             state: QoiCodecState::new(),
             channels,
+            layout: PixelLayout::default(),
+            bytes_per_pixel: channels as usize,
+            pixels_remaining: pixels,
+            pending_pixel: Pixel::new(0, 0, 0, 255),
+            pending_repeats: 0,
+            leftover: [0; RGBA_CHANNELS as usize],
+            leftover_range: 0..0,
+            end_marker_mode: EndMarkerMode::default(),
+            end_marker_valid: None,
         }
     }
 
-    fn read_chunk<'a>(&mut self, buf: &'a mut [u8]) -> std::io::Result<&'a mut [u8]> {
-        let chunk = QoiChunk::decode(&mut self.reader, &self.state);
-        let (pixel, repeats) = self.state.process_chunk(chunk);
+    /// Whether the end marker was valid, this is only known once every pixel has been read.
+    #[must_use]
+    pub fn end_marker_valid(&self) -> Option<bool> {
+        self.end_marker_valid
+    }
 
-        for i in 0..repeats {
-            let i = i * (self.channels as usize);
-            buf[i] = pixel.r();
-            buf[i+1] = pixel.g();
-            buf[i+2] = pixel.b();
-            if self.channels == 4 {
-                buf[i+3] = pixel.a();
+    /// Skips any pixels which haven't been read, checks the end marker, and returns everything
+    /// after it.
+    pub fn finish(mut self) -> Result<Footer, QoiError> {
+        while self.pixels_remaining > 0 {
+            self.next_pixels()?;
+        }
+
+        let end_marker_valid = match self.end_marker_valid {
+            Some(valid) => valid,
+            None => self.check_end_marker()?,
+        };
+
+        Ok(Footer {
+            end_marker_valid,
+            trailing_data: self.reader.collect::<std::io::Result<Vec<u8>>>()?,
+        })
+    }
+
+    fn check_end_marker(&mut self) -> Result<bool, QoiError> {
+        let mut marker = [0u8; END_MARKER.len()];
+        let mut len = 0;
+        while len < marker.len() {
+            match self.reader.next() {
+                Some(byte) => marker[len] = byte?,
+                None => break,
             }
+            len += 1;
         }
-        Ok(&mut buf[((self.channels as usize)*repeats)..])
+
+        let valid = len == marker.len() && marker == END_MARKER;
+        self.end_marker_valid = Some(valid);
+        if !valid && self.end_marker_mode == EndMarkerMode::Strict {
+            return Err(QoiError::MissingEndMarker);
+        }
+        Ok(valid)
+    }
+
+    //A run can't go past the end of the image
+    fn next_pixels(&mut self) -> Result<(Pixel, usize), QoiError> {
+        let chunk = QoiChunk::decode(&mut self.reader, &self.state)?;
+        let (pixel, mut repeats) = self.state.process_chunk(chunk);
+
+        repeats = repeats.min(self.pixels_remaining as usize);
+        self.pixels_remaining -= repeats as u64;
+        Ok((pixel, repeats))
+    }
+
+    //Writes as many pixels as fit in buf, the rest of a run is kept for the next call
+    fn read_chunk<'a>(&mut self, buf: &'a mut [u8]) -> Result<&'a mut [u8], QoiError> {
+        if self.pending_repeats == 0 {
+            let (pixel, repeats) = self.next_pixels()?;
+            self.pending_pixel = pixel;
+            self.pending_repeats = repeats;
+        }
+
+        let bytes_per_pixel = self.bytes_per_pixel;
+        let repeats = self.pending_repeats.min(buf.len() / bytes_per_pixel);
+        let (filled, rest) = buf.split_at_mut(repeats * bytes_per_pixel);
+        for px in filled.chunks_exact_mut(bytes_per_pixel) {
+            self.layout.write(self.pending_pixel, self.channels, px);
+        }
+        self.pending_repeats -= repeats;
+        Ok(rest)
+    }
+
+    fn read_row(&mut self, mut row: &mut [u8]) -> Result<(), QoiError> {
+        while !row.is_empty() {
+            row = self.read_chunk(row)?;
+        }
+        Ok(())
+    }
+
+    fn has_pixels(&self) -> bool {
+        self.pending_repeats > 0 || self.pixels_remaining > 0
     }
 }
 
 impl<R: Read> Read for QoiReader<R> {
-    //This will return self.channels * number of pixels read
+    //This will return however many bytes fit in buf, even if that splits a pixel
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let len = buf.len();
-        let mut ptr = buf;
+        let bytes_per_pixel = self.bytes_per_pixel;
+
+        //The end of a pixel which didn't fit last time
+        let carried = self.leftover_range.len().min(buf.len());
+        let (start, ptr) = buf.split_at_mut(carried);
+        start.copy_from_slice(&self.leftover[self.leftover_range.start..][..carried]);
+        self.leftover_range.start += carried;
+        let mut ptr = ptr;
 
-        while ptr.len() >= self.channels.into() {
+        while ptr.len() >= bytes_per_pixel && self.has_pixels() {
             ptr = self.read_chunk(ptr)?;
         }
 
+        if !ptr.is_empty() && self.leftover_range.is_empty() && self.has_pixels() {
+            let mut pixel = [0u8; RGBA_CHANNELS as usize];
+            self.read_chunk(&mut pixel[..bytes_per_pixel])?;
+
+            let split = ptr.len();
+            ptr.copy_from_slice(&pixel[..split]);
+            ptr = &mut ptr[split..];
+            self.leftover = pixel;
+            self.leftover_range = split..bytes_per_pixel;
+        }
+
+        if !self.has_pixels() && self.leftover_range.is_empty() && self.end_marker_valid.is_none() {
+            self.check_end_marker()?;
+        }
+
         Ok(len - ptr.len())
     }
 }
@@ -134,15 +469,208 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for QoiDecoder<R> {
     type Reader = QoiReader<R>;
 
     fn dimensions(&self) -> (u32, u32) {
-        (self.width, self.height)
+        (self.header.width, self.header.height)
     }
 
     fn color_type(&self) -> image::ColorType {
-        self.colour_type
+        self.layout.color_type(&self.header)
+    }
+
+    //image divides by this, so empty images still claim a byte per row
+    fn scanline_bytes(&self) -> u64 {
+        let row_size = self.header.width as u64 * self.layout.bytes_per_pixel(&self.header) as u64;
+        row_size.max(1)
     }
 
     fn into_reader(self) -> ImageResult<Self::Reader> {
-        let channels = self.color_type().channel_count();
-        Ok(QoiReader::new(self.reader, channels))
+        //color_type can't describe ARGB, so image would mistake the pixels for RGBA
+        if self.layout == PixelLayout::Argb {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormatHint::Name("QOI".to_string()),
+                    UnsupportedErrorKind::GenericFeature("the ARGB pixel layout".to_string()),
+                ),
+            ));
+        }
+        Ok(self.reader())
+    }
+}
+
+enum IncrementalState {
+    Header,
+    Pixels,
+    EndMarker,
+    Finished,
+}
+
+/// Decodes an image from bytes as they arrive, without ever waiting on the rest of the file.
+pub struct QoiIncrementalDecoder {
+    state: IncrementalState,
+    limits: Limits,
+    header: Option<Header>,
+    codec_state: QoiCodecState,
+    //Grows as pixels are decoded, so a header alone can't make it allocate the whole image
+    pixels: Vec<u8>,
+    image_size: usize,
+    //Holds the start of a header, chunk or end marker which was split across calls to feed
+    pending: Vec<u8>,
+}
+
+impl QoiIncrementalDecoder {
+    pub fn new() -> QoiIncrementalDecoder {
+        Self::with_limits(Limits::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> QoiIncrementalDecoder {
+        QoiIncrementalDecoder {
+            state: IncrementalState::Header,
+            limits,
+            header: None,
+            codec_state: QoiCodecState::new(),
+            pixels: Vec::new(),
+            image_size: 0,
+            pending: Vec::with_capacity(HEADER_SIZE),
+        }
+    }
+
+    /// Decodes as much of data as possible, and returns how many pixels have been decoded so far.
+    pub fn feed(&mut self, mut data: &[u8]) -> Result<usize, QoiError> {
+        while !data.is_empty() {
+            data = match self.state {
+                IncrementalState::Header => self.feed_header(data)?,
+                IncrementalState::Pixels => self.feed_pixels(data)?,
+                IncrementalState::EndMarker => self.feed_end_marker(data)?,
+                IncrementalState::Finished => &[],
+            };
+        }
+        Ok(self.pixels_available())
+    }
+
+    //Moves up to len bytes into pending, and returns whether pending has len bytes now
+    fn fill_pending(&mut self, data: &mut &[u8], len: usize) -> bool {
+        let needed = len.saturating_sub(self.pending.len()).min(data.len());
+        self.pending.extend_from_slice(&data[..needed]);
+        *data = &data[needed..];
+        self.pending.len() == len
+    }
+
+    fn feed_header<'a>(&mut self, mut data: &'a [u8]) -> Result<&'a [u8], QoiError> {
+        if self.fill_pending(&mut data, HEADER_SIZE) {
+            let header = Header::from_bytes(&self.pending)?;
+            self.limits.check(&header)?;
+            self.pending.clear();
+
+            self.image_size = header.image_size()?;
+            self.header = Some(header);
+            self.state = if self.image_size == 0 {
+                IncrementalState::EndMarker
+            } else {
+                IncrementalState::Pixels
+            };
+        }
+        Ok(data)
+    }
+
+    fn feed_pixels<'a>(&mut self, mut data: &'a [u8]) -> Result<&'a [u8], QoiError> {
+        //Finish off a chunk which was split across calls first
+        if !self.pending.is_empty() {
+            let size = QoiChunk::size(self.pending[0]);
+            if !self.fill_pending(&mut data, size) {
+                return Ok(data);
+            }
+            let (chunk, _) = QoiChunk::decode_slice(&self.pending, &self.codec_state)?;
+            self.pending.clear();
+            self.write_chunk(chunk);
+        }
+
+        while self.pixels.len() < self.image_size {
+            let flag = match data.first() {
+                Some(flag) => *flag,
+                None => return Ok(data),
+            };
+            if data.len() < QoiChunk::size(flag) {
+                self.pending.extend_from_slice(data);
+                return Ok(&[]);
+            }
+
+            let (chunk, size) = QoiChunk::decode_slice(data, &self.codec_state)?;
+            data = &data[size..];
+            self.write_chunk(chunk);
+        }
+        Ok(data)
+    }
+
+    fn write_chunk(&mut self, chunk: QoiChunk) {
+        let channels = self.header.unwrap().channels as usize;
+
+        //A run can't go past the end of the image
+        let (pixel, repeats) = self.codec_state.process_chunk(chunk);
+        let repeats = repeats.min((self.image_size - self.pixels.len()) / channels);
+        let mut px = [0; RGBA_CHANNELS as usize];
+        pixel.write_to(&mut px[..channels]);
+        for _ in 0..repeats {
+            self.pixels.extend_from_slice(&px[..channels]);
+        }
+
+        if self.pixels.len() == self.image_size {
+            self.state = IncrementalState::EndMarker;
+        }
+    }
+
+    fn feed_end_marker<'a>(&mut self, mut data: &'a [u8]) -> Result<&'a [u8], QoiError> {
+        if self.fill_pending(&mut data, END_MARKER.len()) {
+            if self.pending != END_MARKER {
+                return Err(QoiError::MissingEndMarker);
+            }
+            self.pending.clear();
+            self.state = IncrementalState::Finished;
+        }
+        Ok(data)
+    }
+
+    /// The header, once enough data has been fed to read it.
+    #[must_use]
+    pub fn header(&self) -> Option<Header> {
+        self.header
+    }
+
+    #[must_use]
+    pub fn pixels_available(&self) -> usize {
+        match self.header {
+            Some(header) => self.pixels.len() / header.channels as usize,
+            None => 0,
+        }
+    }
+
+    /// The number of complete rows decoded so far.
+    #[must_use]
+    pub fn rows_available(&self) -> usize {
+        match self.header {
+            Some(header) if header.width > 0 => self.pixels_available() / header.width as usize,
+            _ => 0,
+        }
+    }
+
+    /// The `pixels_available` pixels decoded so far.
+    #[must_use]
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Whether every pixel and the end marker have been decoded.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, IncrementalState::Finished)
+    }
+
+    /// The pixels decoded so far, which is the whole image once `is_finished`.
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+}
+
+impl Default for QoiIncrementalDecoder {
+    fn default() -> Self {
+        Self::new()
     }
 }
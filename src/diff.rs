@@ -0,0 +1,79 @@
+use image::{GrayImage, Luma, RgbaImage};
+
+use crate::error::QoiError;
+
+/// How two images of the same size differ.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageDiff {
+    pub differing_pixels: u64,
+    /// The largest difference in each of the RGBA channels.
+    pub max_delta: [u8; 4],
+    /// Peak signal to noise ratio over every channel in decibels. This is `f64::INFINITY` when the
+    /// images are identical, which includes two empty images.
+    pub psnr: f64,
+}
+
+impl ImageDiff {
+    #[must_use]
+    pub fn is_identical(&self) -> bool {
+        self.differing_pixels == 0
+    }
+}
+
+/// Compares every pixel of a and b.
+pub fn diff(a: &RgbaImage, b: &RgbaImage) -> Result<ImageDiff, QoiError> {
+    check_dimensions(a, b)?;
+
+    let mut differing_pixels = 0;
+    let mut max_delta = [0u8; 4];
+    let mut squared_error = 0u64;
+    for (a, b) in a.pixels().zip(b.pixels()) {
+        if a != b {
+            differing_pixels += 1;
+        }
+        for (i, (a, b)) in a.0.iter().zip(b.0.iter()).enumerate() {
+            let delta = (*a as i16 - *b as i16).unsigned_abs() as u8;
+            max_delta[i] = max_delta[i].max(delta);
+            squared_error += delta as u64 * delta as u64;
+        }
+    }
+
+    Ok(ImageDiff {
+        differing_pixels,
+        max_delta,
+        psnr: psnr(squared_error, a.as_raw().len()),
+    })
+}
+
+//PSNR of 8 bit samples from their summed squared error, infinite when nothing differs
+pub(crate) fn psnr(squared_error: u64, samples: usize) -> f64 {
+    //Empty images have nothing to average over, but they can't differ either
+    if squared_error == 0 {
+        return f64::INFINITY;
+    }
+    let mse = squared_error as f64 / samples as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// An image which is white wherever a and b differ, and black everywhere else.
+pub fn diff_mask(a: &RgbaImage, b: &RgbaImage) -> Result<GrayImage, QoiError> {
+    check_dimensions(a, b)?;
+
+    Ok(GrayImage::from_fn(a.width(), a.height(), |x, y| {
+        if a.get_pixel(x, y) == b.get_pixel(x, y) {
+            Luma([0])
+        } else {
+            Luma([255])
+        }
+    }))
+}
+
+fn check_dimensions(a: &RgbaImage, b: &RgbaImage) -> Result<(), QoiError> {
+    if a.dimensions() != b.dimensions() {
+        return Err(QoiError::DimensionMismatch {
+            expected: a.dimensions(),
+            actual: b.dimensions(),
+        });
+    }
+    Ok(())
+}
@@ -1,43 +1,218 @@
-use std::collections::VecDeque;
-use std::io::Write;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
 
 use image::error::{ImageError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
 use image::ImageEncoder;
 
-use crate::chunks::{QoiChunk, OP_RUN};
 use crate::codec::{ChunkState, QoiCodecState};
 use crate::consts::*;
-use crate::util::Pixel;
+use crate::conversion::to_rgb_or_rgba;
+pub use crate::conversion::Downsample;
+use crate::decoder::decode_from_slice;
+use crate::diff::psnr;
+use crate::error::QoiError;
+use crate::header::{ColorSpace, Header};
+pub use crate::quantize::Quantize;
+use crate::util::{strided_len, Pixel};
+
+fn read_pixel<const CHANNELS: u8>(chunk: &[u8]) -> Pixel {
+    if CHANNELS == RGB_CHANNELS {
+        Pixel::new(chunk[0], chunk[1], chunk[2], 255)
+    } else {
+        Pixel::new(chunk[0], chunk[1], chunk[2], chunk[3])
+    }
+}
+
+fn encode_pixels<const CHANNELS: u8>(
+    buf: &[u8],
+    codec_state: &mut QoiCodecState,
+    mut write_chunk: impl FnMut(ChunkState) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    for chunk in buf.chunks(CHANNELS.into()) {
+        let pixel = read_pixel::<CHANNELS>(chunk);
+
+        for chunk_state in codec_state.process_pixel::<CHANNELS>(pixel) {
+            write_chunk(chunk_state)?;
+        }
+    }
+    Ok(())
+}
+
+/// How many threads `QoiEncoder` splits the image across.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Threads {
+    /// One thread per available core.
+    Auto,
+    /// A fixed number of threads, 0 is treated as 1.
+    Fixed(usize),
+}
+
+impl Threads {
+    fn count(self) -> usize {
+        match self {
+            Threads::Auto => std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            Threads::Fixed(n) => n.max(1),
+        }
+    }
+}
+
+impl Default for Threads {
+    fn default() -> Self {
+        Threads::Fixed(1)
+    }
+}
+
+//A segment of an image encoded on its own, along with the chunks which might turn into an index
+//once it's stitched onto the segments before it
+#[derive(Default)]
+struct EncodedSegment {
+    bytes: Vec<u8>,
+    unresolved: Vec<(Range<usize>, Pixel)>,
+}
+
+impl EncodedSegment {
+    fn write_resolved<W: Write>(&self, state: &QoiCodecState, w: &mut W) -> std::io::Result<()> {
+        let mut written = 0;
+        for (range, pixel) in &self.unresolved {
+            w.write_all(&self.bytes[written..range.start])?;
+            match state.lookup_pixel(pixel) {
+                Some(chunk) => chunk.encode(w)?,
+                None => w.write_all(&self.bytes[range.clone()])?,
+            }
+            written = range.end;
+        }
+        w.write_all(&self.bytes[written..])
+    }
+}
 
 pub struct QoiEncoder<W: Write> {
     w: W,
+    threads: Threads,
+    colour_space: ColorSpace,
+    conversion: Option<Downsample>, //None if only RGB8 and RGBA8 are accepted
+    max_error: u8,
+    quantize: Option<Quantize>,
+}
+
+/// How a lossy encode turned out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EncodeReport {
+    /// Size of the encoded file in bytes.
+    pub size: usize,
+    /// Peak signal to noise ratio of the decoded pixels against the source in decibels, over
+    /// every channel. `f64::INFINITY` if they're identical.
+    pub psnr: f64,
 }
 
 impl<W: Write> QoiEncoder<W> {
     pub fn new(w: W) -> QoiEncoder<W> {
-        QoiEncoder { w }
+        QoiEncoder {
+            w,
+            threads: Threads::default(),
+            colour_space: ColorSpace::default(),
+            conversion: None,
+            max_error: 0,
+            quantize: None,
+        }
     }
 
-    fn to_chunks<const CHANNELS: u8>(buf: &[u8]) -> (VecDeque<ChunkState>, QoiCodecState) {
-        let is_rgb = CHANNELS == RGB_CHANNELS;
+    /// Sets the colour space written to the header, this doesn't change the encoded pixels.
+    pub fn with_color_space(mut self, colour_space: ColorSpace) -> QoiEncoder<W> {
+        self.colour_space = colour_space;
+        self
+    }
 
-        let mut chunks = VecDeque::new();
+    /// Sets the number of threads used to encode an image, the output is the same regardless.
+    pub fn with_threads(mut self, threads: Threads) -> QoiEncoder<W> {
+        self.threads = threads;
+        self
+    }
 
-        let mut codec_state = QoiCodecState::new();
+    /// Lets `write_image` accept grayscale, BGR and 16 bit images by converting them to RGB8 or
+    /// RGBA8 first, with 16 bit channels reduced using downsample.
+    pub fn with_conversion(mut self, downsample: Downsample) -> QoiEncoder<W> {
+        self.conversion = Some(downsample);
+        self
+    }
 
-        for chunk in buf.chunks(CHANNELS.into()) {
-            let pixel = if is_rgb {
-                Pixel::new(chunk[0], chunk[1], chunk[2], 255)
-            } else {
-                Pixel::new(chunk[0], chunk[1], chunk[2], chunk[3])
-            };
+    /// Lets every channel of every pixel be off by up to max_error when that makes the image
+    /// smaller, the output is still a standard QOI file. Lossy images are encoded on one thread.
+    pub fn with_max_error(mut self, max_error: u8) -> QoiEncoder<W> {
+        self.max_error = max_error;
+        self
+    }
 
-            for chunk in codec_state.process_pixel::<CHANNELS>(pixel) {
-                chunks.push_back(chunk);
-            }
+    /// Reduces the image to a palette before encoding it, which is lossy but makes most pixels
+    /// an index or a run. The palette is picked so that its colours don't share index slots.
+    /// `write_image_with_report` shows how much was lost.
+    pub fn with_quantize(mut self, quantize: Quantize) -> QoiEncoder<W> {
+        self.quantize = Some(quantize);
+        self
+    }
+
+    /// Like `write_image`, but decodes the file again to report its size and PSNR, for seeing how
+    /// much `with_quantize` or `with_max_error` lost. Images which aren't RGB8 or RGBA8 are
+    /// compared after being converted to 8 bits.
+    pub fn write_image_with_report(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: image::ColorType,
+    ) -> image::ImageResult<EncodeReport> {
+        let mut encoded = Vec::new();
+        QoiEncoder {
+            w: &mut encoded,
+            threads: self.threads,
+            colour_space: self.colour_space,
+            conversion: self.conversion,
+            max_error: self.max_error,
+            quantize: self.quantize,
         }
+        .write_image(buf, width, height, color_type)?;
+
+        let (_, decoded) = decode_from_slice(&encoded)?;
+        let converted = match (color_type, self.conversion) {
+            (image::ColorType::Rgb8 | image::ColorType::Rgba8, _) | (_, None) => None,
+            (_, Some(downsample)) => to_rgb_or_rgba(buf, width, height, color_type, downsample)?,
+        };
+        let source = converted.as_ref().map_or(buf, |(converted, _)| converted);
+        let squared_error = source
+            .iter()
+            .zip(&decoded)
+            .map(|(&a, &b)| (a.abs_diff(b) as u64).pow(2))
+            .sum();
+
+        self.w.write_all(&encoded)?;
+        Ok(EncodeReport {
+            size: encoded.len(),
+            psnr: psnr(squared_error, decoded.len()),
+        })
+    }
+
+    //Encodes pixels on their own, starting from a state where last_pixel is the pixel before them
+    fn encode_segment<const CHANNELS: u8>(
+        buf: &[u8],
+        last_pixel: Pixel,
+    ) -> std::io::Result<(EncodedSegment, QoiCodecState)> {
+        let mut segment = EncodedSegment::default();
+        let mut codec_state = QoiCodecState::starting_from(last_pixel);
+
+        encode_pixels::<CHANNELS>(buf, &mut codec_state, |chunk_state| {
+            match chunk_state {
+                ChunkState::Resolved(chunk) => chunk.encode(&mut segment.bytes)?,
+                ChunkState::Unresolved(chunk, pixel) => {
+                    let start = segment.bytes.len();
+                    chunk.encode(&mut segment.bytes)?;
+                    segment.unresolved.push((start..segment.bytes.len(), pixel));
+                }
+            }
+            Ok(())
+        })?;
 
-        (chunks, codec_state)
+        Ok((segment, codec_state))
     }
 
     fn encode<const CHANNELS: u8>(
@@ -45,129 +220,192 @@ impl<W: Write> QoiEncoder<W> {
         buf: &[u8],
         width: u32,
         height: u32,
-    ) -> image::ImageResult<()> {
-        //TODO: check if colour_space is actually 0
-        self.write_header(width, height, CHANNELS, 0)
-            .map_err(|e| ImageError::IoError(e))?;
+    ) -> Result<(), QoiError> {
+        let expected = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(CHANNELS as usize))
+            .ok_or(QoiError::DimensionOverflow { width, height })?;
+        if buf.len() != expected {
+            return Err(QoiError::BufferSizeMismatch {
+                expected,
+                actual: buf.len(),
+            });
+        }
 
-        let splits = 2; //TODO: change this
+        let quantized;
+        let buf = match self.quantize {
+            Some(quantize) => {
+                quantized = quantize.apply(buf, width, CHANNELS as usize);
+                &quantized[..]
+            }
+            None => buf,
+        };
 
-        let mut splits = buf
-            .chunks(buf.len() / splits)
-            .map(Self::to_chunks::<CHANNELS>);
+        let header = Header {
+            width,
+            height,
+            channels: CHANNELS,
+            color_space: self.colour_space,
+        };
+        let mut w = BufWriter::new(&mut self.w);
+        w.write_all(&header.to_bytes())?;
 
-        // Stitch all the split up chunks back together
-        let (chunks, mut global_state) = splits.next().unwrap();
+        let channels = CHANNELS as usize;
+        let num_pixels = buf.len() / channels;
+        //Segments start from the source pixel before them, which a lossy decoder won't see
+        let splits = match self.max_error {
+            0 => self.threads.count().min(num_pixels).max(1),
+            _ => 1,
+        };
+        let split_len = num_pixels.div_ceil(splits).max(1) * channels;
 
-        for chunk_state in chunks {
-            let chunk = chunk_state.get_chunk();
-            chunk
-                .encode(&mut self.w)
-                .map_err(|e| ImageError::IoError(e))?;
-        }
+        let mut global_state = QoiCodecState::new().with_max_error(self.max_error);
 
-        for (mut chunks, state) in splits {
-            //Fix first few chunks
+        if splits == 1 {
+            //Nothing gets stitched together, so every chunk can be written straight away
             let initial_state = QoiCodecState::new();
+            encode_pixels::<CHANNELS>(buf, &mut global_state, |chunk_state| {
+                chunk_state.resolve(&initial_state).encode(&mut w)
+            })?;
+        } else {
+            Self::encode_split::<CHANNELS>(buf, split_len, &mut global_state, &mut w)?;
+        }
+
+        if let Some((chunk, _)) = global_state.drain() {
+            chunk.encode(&mut w)?;
+        }
+
+        w.write_all(&END_MARKER)?;
+        //Only the buffer is flushed, flushing the writer itself is up to the caller
+        w.into_inner().map_err(|e| e.into_error())?;
+        Ok(())
+    }
+
+    fn encode_split<const CHANNELS: u8>(
+        buf: &[u8],
+        split_len: usize,
+        global_state: &mut QoiCodecState,
+        w: &mut impl Write,
+    ) -> Result<(), QoiError> {
+        let channels = CHANNELS as usize;
+
+        // Each segment is split into the pixels which continue the run from the previous segment,
+        // and the rest which can be encoded on their own as the pixel before them is known
+        let segments = buf
+            .chunks(split_len)
+            .enumerate()
+            .map(|(i, segment)| {
+                let offset = i * split_len;
+                let last_pixel = if offset == 0 {
+                    QoiCodecState::new().last_pixel()
+                } else {
+                    read_pixel::<CHANNELS>(&buf[(offset - channels)..offset])
+                };
 
-            let dummy = ChunkState::Resolved(QoiChunk::RUN(OP_RUN::new(0)));
-            let first_chunk = std::mem::replace(&mut chunks[0], dummy).get_chunk();
-            let pixel = initial_state.lookup_chunk(first_chunk);
-
-            //Will return upto 2 chunks
-            // 0 => continue the previous run - so we need to fix all following run length encoded chunks
-            // 1 => global_state.run_length = 0 - so we can keep going normally
-            // 2 => previous run ended - so we can keep going normally
-            let mut temp_state = global_state.clone();
-            let mut chunks1 = temp_state.process_pixel::<CHANNELS>(pixel);
-
-            //TODO: Bad hack fix this
-            if temp_state.run_length() > 0 && chunks1.len() == 1 {
-                chunks1
-                    .pop()
-                    .unwrap()
-                    .get_chunk()
-                    .encode(&mut self.w)
-                    .map_err(|e| ImageError::IoError(e))?;
+                let run_end = segment
+                    .chunks(channels)
+                    .position(|chunk| read_pixel::<CHANNELS>(chunk) != last_pixel)
+                    .map_or(segment.len(), |i| i * channels);
+                (&segment[..run_end], &segment[run_end..], last_pixel)
+            })
+            .collect::<Vec<_>>();
+
+        let encode_segment = |&(_, rest, last_pixel): &(&[u8], &[u8], Pixel)| {
+            if rest.is_empty() {
+                Ok(None)
+            } else {
+                Self::encode_segment::<CHANNELS>(rest, last_pixel).map(Some)
             }
+        };
 
-            match chunks1.len() {
-                0 => {
-                    //The split was in the middle of a run
-                    chunks.pop_front(); //Get rid of the dummy chunk
-                    let mut actual_run_length = temp_state.run_length() as u32;
-                    if chunks.len() > 0 {
-                        while let ChunkState::Resolved(QoiChunk::RUN(run)) = &chunks[0] {
-                            actual_run_length += run.run_length() as u32;
-                            chunks.pop_front();
-                        }
-                    }
+        // The first segment is encoded on this thread while the others are spawned
+        let encoded = std::thread::scope(|scope| {
+            let (first, others) = segments.split_at(segments.len().min(1));
+            let handles = others
+                .iter()
+                .map(|segment| scope.spawn(move || encode_segment(segment)))
+                .collect::<Vec<_>>();
 
-                    while actual_run_length > MAX_RUN_LENGTH as u32 {
-                        QoiChunk::RUN(OP_RUN::new(MAX_RUN_LENGTH))
-                            .encode(&mut self.w)
-                            .map_err(|e| ImageError::IoError(e))?;
-                        actual_run_length -= MAX_RUN_LENGTH as u32;
-                    }
-                    if actual_run_length > 0 {
-                        QoiChunk::RUN(OP_RUN::new(actual_run_length as u8))
-                            .encode(&mut self.w)
-                            .map_err(|e| ImageError::IoError(e))?;
-                    }
-                }
-                1 => {
-                    //Normal split
-                    chunks[0] = chunks1.pop().unwrap();
-                }
-                2 => {
-                    //The split ended a run
-                    chunks[0] = chunks1.pop().unwrap();
+            first
+                .iter()
+                .map(encode_segment)
+                .chain(handles.into_iter().map(|handle| handle.join().unwrap()))
+                .collect::<Vec<_>>()
+        });
 
-                    let run = chunks1.pop().unwrap().get_chunk();
-                    run.encode(&mut self.w)
-                        .map_err(|e| ImageError::IoError(e))?;
+        // Stitch all the split up segments back together
+        for ((run, _, _), encoded) in segments.iter().zip(encoded) {
+            for chunk in run.chunks(channels) {
+                let pixel = read_pixel::<CHANNELS>(chunk);
+                for chunk_state in global_state.process_pixel::<CHANNELS>(pixel) {
+                    chunk_state.resolve(global_state).encode(w)?;
                 }
-                _ => unreachable!(),
-            };
-            for chunk_state in chunks {
-                let chunk = match chunk_state {
-                    ChunkState::Resolved(chunk) => chunk,
-                    ChunkState::Unresolved(chunk, pixel) => {
-                        global_state.lookup_pixel(&pixel).unwrap_or(chunk)
-                    }
-                };
-                chunk
-                    .encode(&mut self.w)
-                    .map_err(|e| ImageError::IoError(e))?;
             }
 
-            global_state.merge(state);
-        }
+            if let Some((segment, state)) = encoded? {
+                //The segment starts with a new pixel so any run before it has ended
+                if let Some(chunk) = global_state.flush_run() {
+                    chunk.encode(w)?;
+                }
 
-        if let Some((chunk, _)) = global_state.drain() {
-            chunk
-                .encode(&mut self.w)
-                .map_err(|e| ImageError::IoError(e))?;
+                segment.write_resolved(global_state, w)?;
+                global_state.merge(state);
+            }
         }
-
-        self.w
-            .write(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01])
-            .map_err(|e| ImageError::IoError(e))?;
         Ok(())
     }
 
-    fn write_header(
-        &mut self,
+    /// Encodes an image where each row starts stride bytes after the last. To encode part of a
+    /// larger image, pass the buffer starting at the top left pixel. Padded images are encoded on
+    /// one thread.
+    pub fn write_image_strided(
+        mut self,
+        buf: &[u8],
         width: u32,
         height: u32,
-        channels: u8,
-        color_space: u8,
-    ) -> std::io::Result<()> {
-        self.w.write_all("qoif".as_bytes())?;
-        self.w.write_all(&width.to_be_bytes())?;
-        self.w.write_all(&height.to_be_bytes())?;
-        self.w.write_all(&[channels])?;
-        self.w.write_all(&[color_space])?;
+        stride: usize,
+        color_type: image::ColorType,
+    ) -> image::ImageResult<()> {
+        let row_size = (width as usize)
+            .checked_mul(color_type.bytes_per_pixel() as usize)
+            .ok_or(QoiError::DimensionOverflow { width, height })?;
+        let expected = strided_len(row_size, height, stride)?;
+        if buf.len() < expected {
+            return Err(QoiError::BufferSizeMismatch {
+                expected,
+                actual: buf.len(),
+            }
+            .into());
+        }
+        if stride == row_size {
+            return self.write_image(&buf[..expected], width, height, color_type);
+        }
+
+        let channels = match color_type {
+            image::ColorType::Rgb8 if self.quantize.is_none() => RGB_CHANNELS,
+            image::ColorType::Rgba8 if self.quantize.is_none() => RGBA_CHANNELS,
+            //Everything else is packed so it can be converted or quantized as a whole
+            _ => {
+                let mut packed = Vec::with_capacity(row_size * height as usize);
+                for y in 0..height as usize {
+                    packed.extend_from_slice(&buf[y * stride..][..row_size]);
+                }
+                return self.write_image(&packed, width, height, color_type);
+            }
+        };
+
+        let header = Header {
+            width,
+            height,
+            channels,
+            color_space: self.colour_space,
+        };
+        let mut encoder =
+            QoiStreamEncoder::new(&mut self.w, header)?.with_max_error(self.max_error);
+        for y in 0..height as usize {
+            encoder.write_rows(&buf[y * stride..][..row_size])?;
+        }
+        encoder.finish()?;
         Ok(())
     }
 }
@@ -181,14 +419,135 @@ impl<W: Write> ImageEncoder for QoiEncoder<W> {
         color_type: image::ColorType,
     ) -> image::ImageResult<()> {
         match color_type {
-            image::ColorType::Rgb8 => self.encode::<RGB_CHANNELS>(buf, width, height),
-            image::ColorType::Rgba8 => self.encode::<RGBA_CHANNELS>(buf, width, height),
-            _ => Err(ImageError::Unsupported(
-                UnsupportedError::from_format_and_kind(
-                    ImageFormatHint::Name("Qoi".to_string()),
-                    UnsupportedErrorKind::Color(color_type.into()),
-                ),
-            )),
+            image::ColorType::Rgb8 => Ok(self.encode::<RGB_CHANNELS>(buf, width, height)?),
+            image::ColorType::Rgba8 => Ok(self.encode::<RGBA_CHANNELS>(buf, width, height)?),
+            _ => {
+                let converted = match self.conversion {
+                    Some(downsample) => to_rgb_or_rgba(buf, width, height, color_type, downsample)?,
+                    None => None,
+                };
+                match converted {
+                    Some((buf, RGB_CHANNELS)) => {
+                        Ok(self.encode::<RGB_CHANNELS>(&buf, width, height)?)
+                    }
+                    Some((buf, _)) => Ok(self.encode::<RGBA_CHANNELS>(&buf, width, height)?),
+                    None => Err(ImageError::Unsupported(
+                        UnsupportedError::from_format_and_kind(
+                            ImageFormatHint::Name("Qoi".to_string()),
+                            UnsupportedErrorKind::Color(color_type.into()),
+                        ),
+                    )),
+                }
+            }
         }
     }
 }
+
+/// Encodes an image a few pixels at a time, for when the whole image isn't available at once.
+pub struct QoiStreamEncoder<W: Write> {
+    w: BufWriter<W>,
+    header: Header,
+    codec_state: QoiCodecState,
+    pixels_written: u64,
+    partial_pixel: [u8; RGBA_CHANNELS as usize],
+    partial_len: usize,
+}
+
+impl<W: Write> QoiStreamEncoder<W> {
+    /// Writes the header straight away.
+    pub fn new(w: W, header: Header) -> Result<QoiStreamEncoder<W>, QoiError> {
+        if header.channels != RGB_CHANNELS && header.channels != RGBA_CHANNELS {
+            return Err(QoiError::InvalidChannels(header.channels));
+        }
+
+        let mut w = BufWriter::new(w);
+        w.write_all(&header.to_bytes())?;
+
+        Ok(QoiStreamEncoder {
+            w,
+            header,
+            codec_state: QoiCodecState::new(),
+            pixels_written: 0,
+            partial_pixel: [0; RGBA_CHANNELS as usize],
+            partial_len: 0,
+        })
+    }
+
+    /// Lets every channel of every pixel be off by up to max_error, see
+    /// `QoiEncoder::with_max_error`. This only affects pixels written after it's called.
+    pub fn with_max_error(mut self, max_error: u8) -> QoiStreamEncoder<W> {
+        self.codec_state = self.codec_state.with_max_error(max_error);
+        self
+    }
+
+    /// Encodes the next pixels of the image, buf doesn't have to end on a row or even a pixel.
+    pub fn write_rows(&mut self, mut buf: &[u8]) -> Result<(), QoiError> {
+        let channels = self.header.channels as usize;
+
+        let pixels = (self.partial_len + buf.len()) / channels;
+        let expected = self.header.pixels();
+        if self.pixels_written + pixels as u64 > expected {
+            return Err(QoiError::PixelCountMismatch {
+                expected,
+                actual: self.pixels_written + pixels as u64,
+            });
+        }
+
+        //Finish off a pixel which was split across calls
+        if self.partial_len > 0 {
+            let needed = (channels - self.partial_len).min(buf.len());
+            self.partial_pixel[self.partial_len..(self.partial_len + needed)]
+                .copy_from_slice(&buf[..needed]);
+            self.partial_len += needed;
+            buf = &buf[needed..];
+
+            if self.partial_len < channels {
+                return Ok(());
+            }
+            let pixel = self.partial_pixel;
+            self.encode(&pixel[..channels])?;
+            self.partial_len = 0;
+        }
+
+        let whole = buf.len() - buf.len() % channels;
+        self.encode(&buf[..whole])?;
+
+        let rest = &buf[whole..];
+        self.partial_pixel[..rest.len()].copy_from_slice(rest);
+        self.partial_len = rest.len();
+        Ok(())
+    }
+
+    fn encode(&mut self, buf: &[u8]) -> Result<(), QoiError> {
+        //The state started at the start of the image, so nothing needs to be stitched on
+        let initial_state = QoiCodecState::new();
+        let w = &mut self.w;
+        let write_chunk = |chunk_state: ChunkState| chunk_state.resolve(&initial_state).encode(w);
+
+        match self.header.channels {
+            RGB_CHANNELS => encode_pixels::<RGB_CHANNELS>(buf, &mut self.codec_state, write_chunk)?,
+            _ => encode_pixels::<RGBA_CHANNELS>(buf, &mut self.codec_state, write_chunk)?,
+        }
+        self.pixels_written += (buf.len() / self.header.channels as usize) as u64;
+        Ok(())
+    }
+
+    /// Writes the end marker once every pixel has been written, and returns the writer.
+    pub fn finish(mut self) -> Result<W, QoiError> {
+        let expected = self.header.pixels();
+        if self.pixels_written != expected || self.partial_len > 0 {
+            return Err(QoiError::PixelCountMismatch {
+                expected,
+                actual: self.pixels_written,
+            });
+        }
+
+        if let Some((chunk, _)) = self.codec_state.drain() {
+            chunk.encode(&mut self.w)?;
+        }
+        self.w.write_all(&END_MARKER)?;
+        self.w
+            .into_inner()
+            .map_err(|e| QoiError::Io(e.into_error()))
+    }
+}
@@ -0,0 +1,151 @@
+use std::{error, fmt::Display, io};
+
+use image::error::{
+    DecodingError, ImageFormatHint, LimitError, LimitErrorKind, ParameterError, ParameterErrorKind,
+};
+use image::ImageError;
+
+use crate::ops::Op;
+
+/// Everything that can go wrong while encoding or decoding a QOI image.
+#[derive(Debug)]
+pub enum QoiError {
+    /// The data ended before the header or a chunk was complete.
+    TruncatedData,
+    /// The file doesn't start with `qoif`.
+    InvalidMagic([u8; 4]),
+    /// The channel count in the header isn't 3 or 4.
+    InvalidChannels(u8),
+    /// The colour space in the header isn't 0 or 1.
+    InvalidColorSpace(u8),
+    /// The 8 byte end marker is missing or garbled.
+    MissingEndMarker,
+    /// The image has too many pixels to be addressed on this platform.
+    DimensionOverflow {
+        width: u32,
+        height: u32,
+    },
+    /// The image is larger than the decoder's `Limits` allow.
+    LimitsExceeded {
+        width: u32,
+        height: u32,
+    },
+    /// A different number of pixels were supplied than the header has room for.
+    PixelCountMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// The pixel buffer doesn't match the image's dimensions.
+    BufferSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// The row stride of a buffer is shorter than a row of pixels, or too large to address.
+    InvalidStride {
+        stride: usize,
+        row_size: usize,
+    },
+    /// Two images which should be the same size aren't.
+    DimensionMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// An op has a value which can't be encoded.
+    InvalidOp(Op),
+    Io(io::Error),
+}
+
+impl error::Error for QoiError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            QoiError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for QoiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QoiError::TruncatedData => write!(f, "Data ended unexpectedly"),
+            QoiError::InvalidMagic(magic) => write!(f, "Invalid magic bytes {:?}", magic),
+            QoiError::InvalidChannels(channels) => write!(f, "Invalid channel count {}", channels),
+            QoiError::InvalidColorSpace(colour_space) => {
+                write!(f, "Invalid colour space {}", colour_space)
+            }
+            QoiError::MissingEndMarker => write!(f, "Missing end marker"),
+            QoiError::DimensionOverflow { width, height } => {
+                write!(f, "Image dimensions {}x{} are too large", width, height)
+            }
+            QoiError::LimitsExceeded { width, height } => {
+                write!(f, "Image dimensions {}x{} exceed the limits", width, height)
+            }
+            QoiError::PixelCountMismatch { expected, actual } => {
+                write!(f, "Expected {} pixels but got {} pixels", expected, actual)
+            }
+            QoiError::BufferSizeMismatch { expected, actual } => write!(
+                f,
+                "Expected a buffer of {} bytes but got {} bytes",
+                expected, actual
+            ),
+            QoiError::InvalidStride { stride, row_size } => write!(
+                f,
+                "Invalid stride of {} bytes for rows of {} bytes",
+                stride, row_size
+            ),
+            QoiError::DimensionMismatch { expected, actual } => write!(
+                f,
+                "Expected a {}x{} image but got a {}x{} image",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            QoiError::InvalidOp(op) => write!(f, "{:?} is out of range", op),
+            QoiError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for QoiError {
+    fn from(e: io::Error) -> QoiError {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => QoiError::TruncatedData,
+            //QoiReader has to hide decoding errors inside io::Errors
+            io::ErrorKind::InvalidData if e.get_ref().is_some_and(|e| e.is::<QoiError>()) => {
+                *e.into_inner().unwrap().downcast::<QoiError>().unwrap()
+            }
+            _ => QoiError::Io(e),
+        }
+    }
+}
+
+impl From<QoiError> for io::Error {
+    fn from(e: QoiError) -> io::Error {
+        match e {
+            QoiError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+impl From<QoiError> for ImageError {
+    fn from(e: QoiError) -> ImageError {
+        match e {
+            QoiError::Io(e) => ImageError::IoError(e),
+            QoiError::DimensionOverflow { .. } => {
+                ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError))
+            }
+            QoiError::LimitsExceeded { .. } => {
+                ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory))
+            }
+            QoiError::PixelCountMismatch { .. }
+            | QoiError::BufferSizeMismatch { .. }
+            | QoiError::InvalidStride { .. }
+            | QoiError::DimensionMismatch { .. } => ImageError::Parameter(
+                ParameterError::from_kind(ParameterErrorKind::DimensionMismatch),
+            ),
+            e => ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::Name("QOI".to_string()),
+                e,
+            )),
+        }
+    }
+}
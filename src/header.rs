@@ -0,0 +1,121 @@
+use std::convert::{TryFrom, TryInto};
+
+use crate::consts::{MAX_RUN_LENGTH, RGBA_CHANNELS, RGB_CHANNELS};
+use crate::error::QoiError;
+
+pub const HEADER_SIZE: usize = 14;
+pub(crate) const MAGIC: &[u8; 4] = b"qoif";
+
+/// The colour space byte of a QOI header. It is purely informative and doesn't change how pixels
+/// are encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// sRGB colour channels with a linear alpha channel.
+    #[default]
+    Srgb,
+    /// Every channel is linear.
+    Linear,
+}
+
+impl From<ColorSpace> for u8 {
+    fn from(colour_space: ColorSpace) -> u8 {
+        match colour_space {
+            ColorSpace::Srgb => 0,
+            ColorSpace::Linear => 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorSpace {
+    type Error = QoiError;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        match byte {
+            0 => Ok(ColorSpace::Srgb),
+            1 => Ok(ColorSpace::Linear),
+            _ => Err(QoiError::InvalidColorSpace(byte)),
+        }
+    }
+}
+
+/// The 14 byte header at the start of every QOI image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    /// Either 3 for RGB or 4 for RGBA.
+    pub channels: u8,
+    pub color_space: ColorSpace,
+}
+
+impl Header {
+    /// Parses and validates the first 14 bytes of buf.
+    pub fn from_bytes(buf: &[u8]) -> Result<Header, QoiError> {
+        let buf = buf.get(..HEADER_SIZE).ok_or(QoiError::TruncatedData)?;
+
+        if &buf[0..4] != MAGIC {
+            return Err(QoiError::InvalidMagic(buf[0..4].try_into().unwrap()));
+        }
+
+        let header = Header {
+            width: u32::from_be_bytes(buf[4..8].try_into().unwrap()),
+            height: u32::from_be_bytes(buf[8..12].try_into().unwrap()),
+            channels: buf[12],
+            color_space: ColorSpace::try_from(buf[13])?,
+        };
+
+        if header.channels != RGB_CHANNELS && header.channels != RGBA_CHANNELS {
+            return Err(QoiError::InvalidChannels(header.channels));
+        }
+
+        //The whole image has to fit in memory when it's decoded
+        header.image_size()?;
+
+        Ok(header)
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0u8; HEADER_SIZE];
+        buf[0..4].copy_from_slice(MAGIC);
+        buf[4..8].copy_from_slice(&self.width.to_be_bytes());
+        buf[8..12].copy_from_slice(&self.height.to_be_bytes());
+        buf[12] = self.channels;
+        buf[13] = self.color_space.into();
+        buf
+    }
+
+    #[must_use]
+    pub fn pixels(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// The number of bytes the decoded image takes up.
+    pub fn image_size(&self) -> Result<usize, QoiError> {
+        (self.width as usize)
+            .checked_mul(self.height as usize)
+            .and_then(|pixels| pixels.checked_mul(self.channels as usize))
+            .ok_or(QoiError::DimensionOverflow {
+                width: self.width,
+                height: self.height,
+            })
+    }
+
+    /// Checks that a file of data_len bytes, header included, could hold every pixel. Run this
+    /// before allocating for an untrusted header.
+    pub fn check_plausible(&self, data_len: usize) -> Result<(), QoiError> {
+        //Every byte holds at most one full run, so don't allocate for pixels that can't be there
+        let max_pixels = data_len.saturating_sub(HEADER_SIZE) as u64 * MAX_RUN_LENGTH as u64;
+        if self.pixels() > max_pixels {
+            return Err(QoiError::TruncatedData);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn color_type(&self) -> image::ColorType {
+        if self.channels == RGB_CHANNELS {
+            image::ColorType::Rgb8
+        } else {
+            image::ColorType::Rgba8
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+
+use image::{DynamicImage, GenericImageView, ImageBuffer, ImageEncoder, ImageFormat, ImageResult};
+
+use crate::decoder::decode_from_slice;
+use crate::encoder::QoiEncoder;
+use crate::header::MAGIC;
+
+/// Whether data starts with QOI's magic bytes.
+#[must_use]
+pub fn is_qoi(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Decodes an image held in memory, QOI images are decoded by this crate and everything else by
+/// `image`.
+pub fn load_from_memory(data: &[u8]) -> ImageResult<DynamicImage> {
+    if !is_qoi(data) {
+        return image::load_from_memory(data);
+    }
+
+    let (header, pixels) = decode_from_slice(data)?;
+    //decode_from_slice always returns a buffer of exactly the right size
+    Ok(if header.channels == 4 {
+        DynamicImage::ImageRgba8(
+            ImageBuffer::from_raw(header.width, header.height, pixels).unwrap(),
+        )
+    } else {
+        DynamicImage::ImageRgb8(ImageBuffer::from_raw(header.width, header.height, pixels).unwrap())
+    })
+}
+
+/// Opens an image of any format, QOI files are recognised by their magic bytes.
+pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<DynamicImage> {
+    let data = std::fs::read(&path)?;
+    if is_qoi(&data) {
+        return load_from_memory(&data);
+    }
+
+    //Some formats don't have magic bytes, so the extension is used as a fallback
+    let mut reader = image::io::Reader::new(Cursor::new(data));
+    if let Ok(format) = ImageFormat::from_path(&path) {
+        reader.set_format(format);
+    }
+    reader.with_guessed_format()?.decode()
+}
+
+/// Saves an image, it's encoded as QOI if the path ends in `.qoi` and by `image` otherwise.
+pub fn save<P: AsRef<Path>>(image: &DynamicImage, path: P) -> ImageResult<()> {
+    let path = path.as_ref();
+    let is_qoi_path = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("qoi"));
+    if !is_qoi_path {
+        return image.save(path);
+    }
+
+    let mut w = BufWriter::new(File::create(path)?);
+    write_to(image, &mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Encodes an image as QOI, anything other than RGB8 or RGBA8 is converted first.
+pub fn write_to<W: Write>(image: &DynamicImage, w: W) -> ImageResult<()> {
+    let (width, height) = image.dimensions();
+    let encoder = QoiEncoder::new(w);
+    match image {
+        DynamicImage::ImageRgb8(buf) => {
+            encoder.write_image(buf, width, height, image::ColorType::Rgb8)
+        }
+        DynamicImage::ImageRgba8(buf) => {
+            encoder.write_image(buf, width, height, image::ColorType::Rgba8)
+        }
+        //Only keep the alpha channel if there is one
+        image if image.color().has_alpha() => {
+            encoder.write_image(&image.to_rgba8(), width, height, image::ColorType::Rgba8)
+        }
+        image => encoder.write_image(&image.to_rgb8(), width, height, image::ColorType::Rgb8),
+    }
+}
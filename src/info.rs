@@ -0,0 +1,128 @@
+use image::{Rgb, RgbImage};
+
+use crate::consts::{END_MARKER, MAX_RUN_LENGTH};
+use crate::error::QoiError;
+use crate::header::Header;
+use crate::ops::{Op, QoiTokenizer};
+
+/// How many times each kind of op appears in an image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpCounts {
+    pub rgb: u64,
+    pub rgba: u64,
+    pub index: u64,
+    pub diff: u64,
+    pub luma: u64,
+    pub run: u64,
+}
+
+impl OpCounts {
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.rgb + self.rgba + self.index + self.diff + self.luma + self.run
+    }
+}
+
+/// Statistics about how an image was encoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QoiInfo {
+    pub header: Header,
+    /// Size of the whole file in bytes.
+    pub file_size: usize,
+    pub ops: OpCounts,
+    /// `run_lengths[i]` is how many runs of length `i + 1` there are.
+    pub run_lengths: [u64; MAX_RUN_LENGTH as usize],
+    pub end_marker_valid: bool,
+}
+
+impl QoiInfo {
+    /// None for an image without any pixels.
+    #[must_use]
+    pub fn bits_per_pixel(&self) -> Option<f64> {
+        match self.header.pixels() {
+            0 => None,
+            pixels => Some(self.file_size as f64 * 8.0 / pixels as f64),
+        }
+    }
+
+    /// The size of the raw pixels divided by the size of the file, 0 for an image without any
+    /// pixels. The file always has a header, so this is never infinite.
+    #[must_use]
+    pub fn compression_ratio(&self) -> f64 {
+        (self.header.pixels() * self.header.channels as u64) as f64 / self.file_size as f64
+    }
+
+    /// The fraction of pixels outside of runs which were found in the index, None if every op is
+    /// a run.
+    #[must_use]
+    pub fn index_hit_rate(&self) -> Option<f64> {
+        match self.ops.total() - self.ops.run {
+            0 => None,
+            ops => Some(self.ops.index as f64 / ops as f64),
+        }
+    }
+}
+
+/// Walks every op in a QOI file and counts them.
+pub fn inspect(data: &[u8]) -> Result<QoiInfo, QoiError> {
+    let mut tokenizer = QoiTokenizer::new(data)?;
+    let mut ops = OpCounts::default();
+    let mut run_lengths = [0; MAX_RUN_LENGTH as usize];
+
+    for token in tokenizer.by_ref() {
+        match token?.op {
+            Op::Rgb { .. } => ops.rgb += 1,
+            Op::Rgba { .. } => ops.rgba += 1,
+            Op::Index(_) => ops.index += 1,
+            Op::Diff { .. } => ops.diff += 1,
+            Op::Luma { .. } => ops.luma += 1,
+            Op::Run(run) => {
+                ops.run += 1;
+                run_lengths[run as usize - 1] += 1;
+            }
+        }
+    }
+
+    let end = tokenizer.offset();
+    Ok(QoiInfo {
+        header: tokenizer.header(),
+        file_size: data.len(),
+        ops,
+        run_lengths,
+        end_marker_valid: data.get(end..end + END_MARKER.len()) == Some(&END_MARKER[..]),
+    })
+}
+
+/// Decodes a QOI file into an image where every pixel is coloured by the op that produced it:
+/// runs are blue, index hits green, diffs yellow, lumas orange, RGB red and RGBA magenta.
+pub fn heat_map(data: &[u8]) -> Result<RgbImage, QoiError> {
+    let tokenizer = QoiTokenizer::new(data)?;
+    let header = tokenizer.header();
+
+    header.check_plausible(data.len())?;
+
+    let mut image = RgbImage::new(header.width, header.height);
+    let pixels = header.pixels();
+    for token in tokenizer {
+        let token = token?;
+        let colour = heat_map_colour(&token.op);
+
+        //The last run can go past the end of the image
+        for i in token.pixel..(token.pixel + token.count).min(pixels) {
+            let (x, y) = (i % header.width as u64, i / header.width as u64);
+            image.put_pixel(x as u32, y as u32, colour);
+        }
+    }
+    Ok(image)
+}
+
+fn heat_map_colour(op: &Op) -> Rgb<u8> {
+    Rgb(match op {
+        Op::Run(_) => [0, 90, 255],
+        Op::Index(_) => [0, 200, 80],
+        Op::Diff { .. } => [255, 230, 0],
+        Op::Luma { .. } => [255, 140, 0],
+        Op::Rgb { .. } => [220, 0, 0],
+        Op::Rgba { .. } => [200, 0, 200],
+    })
+}
@@ -1,10 +1,21 @@
 mod chunks;
 mod consts;
+mod conversion;
 pub mod decoder;
+pub mod diff;
 pub mod encoder;
+pub mod error;
+pub mod header;
+mod image_io;
+pub mod info;
+pub mod ops;
+mod quantize;
 mod util;
+pub mod verify;
 mod codec;
 
+pub use image_io::{is_qoi, load_from_memory, open, save, write_to};
+
 #[cfg(test)]
 mod tests {
     use std::io::BufWriter;
@@ -12,7 +23,7 @@ mod tests {
     use image::io::Reader as ImageReader;
     use image::{GenericImageView, ImageDecoder, ImageEncoder};
 
-    use crate::encoder::QoiEncoder;
+    use crate::encoder::{QoiEncoder, Threads};
 
     fn get_images(file_ext: &str) -> Vec<String> {
         let paths = std::fs::read_dir("./qoi_test_images").unwrap();
@@ -23,11 +34,47 @@ mod tests {
             .collect::<Vec<String>>()
     }
 
+    //Noisy image with long runs and repeated colours so segments split across every kind of chunk
+    fn synthetic_image(channels: usize, num_pixels: usize) -> Vec<u8> {
+        let mut seed = 12345u32;
+        let mut pixel = vec![0u8; channels];
+        let mut bytes = Vec::with_capacity(num_pixels * channels);
+
+        for _ in 0..num_pixels {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            match (seed >> 16) % 8 {
+                0..=3 => {}
+                4 => pixel[1] = pixel[1].wrapping_add(1),
+                5 => pixel[0] = pixel[0].wrapping_sub(20),
+                6 => pixel
+                    .iter_mut()
+                    .for_each(|c| *c = (seed >> 24) as u8 & 0b11),
+                _ => pixel[channels - 1] = (seed >> 8) as u8,
+            }
+            bytes.extend_from_slice(&pixel);
+        }
+        bytes
+    }
+
+    //The pixels of a 100x50 RGBA synthetic image and its encoding
+    fn encoded_fixture() -> (Vec<u8>, Vec<u8>) {
+        let bytes = synthetic_image(4, 5000);
+        let mut img = Vec::new();
+        QoiEncoder::new(&mut img)
+            .write_image(&bytes, 100, 50, image::ColorType::Rgba8)
+            .unwrap();
+        (bytes, img)
+    }
+
     #[cfg(test)]
     mod encoding_tests {
         use image::EncodableLayout;
 
         use super::*;
+        use crate::decoder::decode_from_slice;
+        use crate::encoder::{Downsample, QoiStreamEncoder};
+        use crate::error::QoiError;
+        use crate::header::{ColorSpace, Header};
 
         fn strip(buf: &[u8]) -> &[u8] {
             &buf[14..(buf.len() - 8)] //14 byte header and 8 byte footer
@@ -79,6 +126,362 @@ mod tests {
             assert!(pixel_buf == [254, 100, 100, 0, 254, 150, 100, 0, 21, 43])
         }
 
+        fn encode_with_threads(
+            bytes: &[u8],
+            color_type: image::ColorType,
+            threads: Threads,
+        ) -> Vec<u8> {
+            let mut out = Vec::new();
+            let num_pixels = bytes.len() / color_type.bytes_per_pixel() as usize;
+            QoiEncoder::new(&mut out)
+                .with_threads(threads)
+                .write_image(bytes, num_pixels as u32, 1, color_type)
+                .unwrap();
+            out
+        }
+
+        #[test]
+        fn test_threaded_matches_single_threaded() {
+            for &(channels, color_type) in
+                &[(3, image::ColorType::Rgb8), (4, image::ColorType::Rgba8)]
+            {
+                for &num_pixels in &[0, 1, 2, 5, 63, 64, 200, 5000] {
+                    let bytes = synthetic_image(channels, num_pixels);
+                    let reference = encode_with_threads(&bytes, color_type, Threads::Fixed(1));
+
+                    for threads in 2..=9 {
+                        let encoded =
+                            encode_with_threads(&bytes, color_type, Threads::Fixed(threads));
+                        assert_eq!(
+                            reference, encoded,
+                            "{} pixels on {} threads",
+                            num_pixels, threads
+                        );
+                    }
+                    assert_eq!(
+                        reference,
+                        encode_with_threads(&bytes, color_type, Threads::Auto)
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn test_threaded_run_across_segments() {
+            //One long run gets split over every segment
+            let bytes = [7u8, 8, 9].repeat(500);
+            let reference = encode_with_threads(&bytes, image::ColorType::Rgb8, Threads::Fixed(1));
+            let encoded = encode_with_threads(&bytes, image::ColorType::Rgb8, Threads::Fixed(8));
+            assert_eq!(reference, encoded);
+        }
+
+        fn stream_header(channels: u8, width: u32, height: u32) -> Header {
+            Header {
+                width,
+                height,
+                channels,
+                color_space: ColorSpace::Srgb,
+            }
+        }
+
+        #[test]
+        fn test_stream_encoder_matches_encoder() {
+            for &(channels, color_type) in
+                &[(3, image::ColorType::Rgb8), (4, image::ColorType::Rgba8)]
+            {
+                let bytes = synthetic_image(channels as usize, 5000);
+                let reference = encode_with_threads(&bytes, color_type, Threads::Fixed(1));
+
+                //Whole rows, single bytes and spans which split pixels
+                for &span in &[50 * channels as usize, 1, 7, 1000] {
+                    let mut encoder =
+                        QoiStreamEncoder::new(Vec::new(), stream_header(channels, 5000, 1))
+                            .unwrap();
+                    for rows in bytes.chunks(span) {
+                        encoder.write_rows(rows).unwrap();
+                    }
+                    assert_eq!(encoder.finish().unwrap(), reference, "spans of {}", span);
+                }
+            }
+        }
+
+        #[test]
+        fn test_stream_encoder_pixel_count() {
+            let mut encoder = QoiStreamEncoder::new(Vec::new(), stream_header(3, 2, 2)).unwrap();
+            encoder.write_rows(&[0; 6]).unwrap();
+            let result = encoder.write_rows(&[0; 9]);
+            assert!(matches!(
+                result,
+                Err(QoiError::PixelCountMismatch {
+                    expected: 4,
+                    actual: 5
+                })
+            ));
+
+            //Part of the last pixel is still missing
+            encoder.write_rows(&[0; 4]).unwrap();
+            assert!(matches!(
+                encoder.finish(),
+                Err(QoiError::PixelCountMismatch { .. })
+            ));
+
+            let result = QoiStreamEncoder::new(Vec::new(), stream_header(2, 2, 2));
+            assert!(matches!(result, Err(QoiError::InvalidChannels(2))));
+        }
+
+        fn encode_as(buf: &[u8], width: u32, color_type: image::ColorType) -> Vec<u8> {
+            let height =
+                (buf.len() / width as usize / color_type.bytes_per_pixel() as usize) as u32;
+            let mut out = Vec::new();
+            QoiEncoder::new(&mut out)
+                .with_conversion(Downsample::Round)
+                .write_image(buf, width, height, color_type)
+                .unwrap();
+            out
+        }
+
+        #[test]
+        fn test_colour_conversion() {
+            let rgb = synthetic_image(3, 60);
+            let rgba = synthetic_image(4, 60);
+            let rgb_qoi = encode_as(&rgb, 6, image::ColorType::Rgb8);
+            let rgba_qoi = encode_as(&rgba, 6, image::ColorType::Rgba8);
+
+            let bgr: Vec<u8> = rgb.chunks(3).flat_map(|p| [p[2], p[1], p[0]]).collect();
+            assert_eq!(encode_as(&bgr, 6, image::ColorType::Bgr8), rgb_qoi);
+            let bgra: Vec<u8> = rgba
+                .chunks(4)
+                .flat_map(|p| [p[2], p[1], p[0], p[3]])
+                .collect();
+            assert_eq!(encode_as(&bgra, 6, image::ColorType::Bgra8), rgba_qoi);
+
+            //Every 8 bit value is exactly representable in 16 bits
+            let rgb16: Vec<u8> = rgb
+                .iter()
+                .flat_map(|&c| (c as u16 * 257).to_ne_bytes())
+                .collect();
+            assert_eq!(encode_as(&rgb16, 6, image::ColorType::Rgb16), rgb_qoi);
+            let rgba16: Vec<u8> = rgba
+                .iter()
+                .flat_map(|&c| (c as u16 * 257).to_ne_bytes())
+                .collect();
+            assert_eq!(encode_as(&rgba16, 6, image::ColorType::Rgba16), rgba_qoi);
+
+            let grey: Vec<u8> = rgb.iter().step_by(3).copied().collect();
+            let grey_rgb: Vec<u8> = grey.iter().flat_map(|&c| [c, c, c]).collect();
+            assert_eq!(
+                encode_as(&grey, 6, image::ColorType::L8),
+                encode_as(&grey_rgb, 6, image::ColorType::Rgb8)
+            );
+            let grey_alpha: Vec<u8> = rgba.chunks(4).flat_map(|p| [p[0], p[3]]).collect();
+            let grey_rgba: Vec<u8> = rgba
+                .chunks(4)
+                .flat_map(|p| [p[0], p[0], p[0], p[3]])
+                .collect();
+            assert_eq!(
+                encode_as(&grey_alpha, 6, image::ColorType::La8),
+                encode_as(&grey_rgba, 6, image::ColorType::Rgba8)
+            );
+
+            //Conversion has to be asked for
+            let result =
+                QoiEncoder::new(Vec::new()).write_image(&grey, 6, 10, image::ColorType::L8);
+            assert!(matches!(result, Err(image::ImageError::Unsupported(_))));
+        }
+
+        #[test]
+        fn test_downsample() {
+            //Halfway between 128 and 129, with an opaque alpha
+            let halfway = 128 * 257 + 128;
+            let rgba16: Vec<u8> = [halfway, halfway, halfway, u16::MAX]
+                .repeat(16)
+                .iter()
+                .flat_map(|c| c.to_ne_bytes())
+                .collect();
+
+            let (_, rounded) =
+                decode_from_slice(&encode_as(&rgba16, 4, image::ColorType::Rgba16)).unwrap();
+            assert!(rounded.chunks(4).all(|p| p == [128, 128, 128, 255]));
+
+            let mut out = Vec::new();
+            QoiEncoder::new(&mut out)
+                .with_conversion(Downsample::Dither)
+                .write_image(&rgba16, 4, 4, image::ColorType::Rgba16)
+                .unwrap();
+            let (_, dithered) = decode_from_slice(&out).unwrap();
+            //Half of each 4x4 block rounds up
+            let ups = dithered.chunks(4).filter(|p| p[0] == 129).count();
+            assert_eq!(ups, 8);
+            assert!(dithered
+                .chunks(4)
+                .all(|p| p[0] >= 128 && p[0] <= 129 && p[3] == 255));
+        }
+
+        #[test]
+        fn test_encoder_buffer_size() {
+            let result =
+                QoiEncoder::new(Vec::new()).write_image(&[0; 11], 2, 2, image::ColorType::Rgb8);
+            assert!(matches!(result, Err(image::ImageError::Parameter(_))));
+        }
+
+        #[test]
+        fn test_max_error() {
+            for &channels in &[3, 4] {
+                let colour_type = match channels {
+                    3 => image::ColorType::Rgb8,
+                    _ => image::ColorType::Rgba8,
+                };
+                let bytes = synthetic_image(channels, 4000);
+                let encode = |max_error: u8, threads: Threads| {
+                    let mut out = Vec::new();
+                    QoiEncoder::new(&mut out)
+                        .with_max_error(max_error)
+                        .with_threads(threads)
+                        .write_image(&bytes, 80, 50, colour_type)
+                        .unwrap();
+                    out
+                };
+
+                let lossless = encode(0, Threads::Fixed(1));
+                assert_eq!(encode(0, Threads::Fixed(4)), lossless);
+
+                for &max_error in &[1, 4, 30] {
+                    let lossy = encode(max_error, Threads::Fixed(1));
+                    assert!(lossy.len() < lossless.len());
+                    assert_eq!(encode(max_error, Threads::Fixed(4)), lossy);
+                    assert!(crate::verify::verify(&lossy).is_valid());
+
+                    let (_, decoded) = decode_from_slice(&lossy).unwrap();
+                    assert!(decoded
+                        .iter()
+                        .zip(&bytes)
+                        .all(|(a, b)| a.abs_diff(*b) <= max_error));
+                }
+            }
+        }
+
+        #[test]
+        fn test_quantize() {
+            use std::collections::HashSet;
+
+            use crate::encoder::Quantize;
+            use crate::util::Pixel;
+
+            let gradient: Vec<u8> = (0..64 * 64)
+                .flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, (i * 7 % 256) as u8])
+                .collect();
+            let encode = |buf: &[u8], quantize: Quantize| {
+                let mut out = Vec::new();
+                QoiEncoder::new(&mut out)
+                    .with_quantize(quantize)
+                    .write_image(buf, 64, 64, image::ColorType::Rgb8)
+                    .unwrap();
+                out
+            };
+            let lossless = encode_as(&gradient, 64, image::ColorType::Rgb8);
+
+            for &dither in &[false, true] {
+                let quantize = Quantize { colors: 16, dither };
+                let quantized = encode(&gradient, quantize);
+                assert!(quantized.len() < lossless.len());
+
+                //Every colour has its own slot in the index
+                let (_, decoded) = decode_from_slice(&quantized).unwrap();
+                let colours: HashSet<&[u8]> = decoded.chunks(3).collect();
+                let slots: HashSet<usize> = colours
+                    .iter()
+                    .map(|c| Pixel::new(c[0], c[1], c[2], 255).hash())
+                    .collect();
+                assert!(colours.len() <= 16);
+                assert_eq!(slots.len(), colours.len());
+
+                let mut strided = Vec::new();
+                QoiEncoder::new(&mut strided)
+                    .with_quantize(quantize)
+                    .write_image_strided(&gradient, 64, 32, 64 * 6, image::ColorType::Rgb8)
+                    .unwrap();
+                let (_, decoded) = decode_from_slice(&strided).unwrap();
+                assert!(decoded.chunks(3).collect::<HashSet<_>>().len() <= 16);
+            }
+
+            //The report matches the file, and the pixels it decodes to
+            let mut out = Vec::new();
+            let quantize = Quantize {
+                colors: 16,
+                dither: false,
+            };
+            let report = QoiEncoder::new(&mut out)
+                .with_quantize(quantize)
+                .write_image_with_report(&gradient, 64, 64, image::ColorType::Rgb8)
+                .unwrap();
+            assert_eq!(out, encode(&gradient, quantize));
+            assert_eq!(report.size, out.len());
+            let (_, decoded) = decode_from_slice(&out).unwrap();
+            let squared_error: u64 = decoded
+                .iter()
+                .zip(&gradient)
+                .map(|(&a, &b)| (a.abs_diff(b) as u64).pow(2))
+                .sum();
+            let mse = squared_error as f64 / gradient.len() as f64;
+            assert_eq!(report.psnr, 10.0 * (255.0f64 * 255.0 / mse).log10());
+
+            //Images with few enough colours are untouched
+            let few: Vec<u8> = (0..64 * 64u32)
+                .flat_map(|i| [(i / 400 * 20) as u8, 0, (i % 3 * 100) as u8])
+                .collect();
+            let (_, decoded) = decode_from_slice(&encode(&few, Quantize::default())).unwrap();
+            assert_eq!(decoded, few);
+            let report = QoiEncoder::new(Vec::new())
+                .with_quantize(Quantize::default())
+                .write_image_with_report(&few, 64, 64, image::ColorType::Rgb8)
+                .unwrap();
+            assert_eq!(report.psnr, f64::INFINITY);
+        }
+
+        #[test]
+        fn test_strided_encode() {
+            let bytes = synthetic_image(3, 600);
+            let mut packed = Vec::new();
+            QoiEncoder::new(&mut packed)
+                .write_image(&bytes, 30, 20, image::ColorType::Rgb8)
+                .unwrap();
+
+            //Each row is padded to 100 bytes, and the last row isn't padded at all
+            let mut padded = Vec::new();
+            for row in bytes.chunks(90) {
+                padded.extend_from_slice(row);
+                padded.extend_from_slice(&[0xAA; 10]);
+            }
+            padded.truncate(padded.len() - 10);
+            let mut strided = Vec::new();
+            QoiEncoder::new(&mut strided)
+                .write_image_strided(&padded, 30, 20, 100, image::ColorType::Rgb8)
+                .unwrap();
+            assert_eq!(strided, packed);
+
+            //The right half of the image
+            let mut right = Vec::new();
+            QoiEncoder::new(&mut right)
+                .write_image_strided(&bytes[45..], 15, 20, 90, image::ColorType::Rgb8)
+                .unwrap();
+            let (_, decoded) = decode_from_slice(&right).unwrap();
+            let expected: Vec<u8> = bytes
+                .chunks(90)
+                .flat_map(|row| &row[45..])
+                .copied()
+                .collect();
+            assert_eq!(decoded, expected);
+
+            let result = QoiEncoder::new(Vec::new()).write_image_strided(
+                &padded,
+                30,
+                20,
+                80,
+                image::ColorType::Rgb8,
+            );
+            assert!(matches!(result, Err(image::ImageError::Parameter(_))));
+        }
+
         #[test]
         fn test_images() {
             for fname in get_images(".png") {
@@ -108,18 +511,614 @@ mod tests {
         use std::io::Read;
 
         use super::*;
-        use crate::decoder::{QoiDecoder, QoiReader};
+        use crate::decoder::{
+            decode_from_slice, decode_into, EndMarkerMode, Limits, PixelLayout, QoiDecoder,
+            QoiIncrementalDecoder, QoiReader,
+        };
+        use crate::error::QoiError;
+        use crate::header::ColorSpace;
 
         #[test]
         fn test_rle_decoding() {
             //254 is OP_RGB and the weird | thing is OP_RUN
-            let img = Vec::from([254, 100, 100, 0, 0b1100_0000 | (3 - 1)]);
-            let mut decoder = QoiReader::new(&img[..], 3);
+            let mut img = Vec::from([254, 100, 100, 0, 0b1100_0000 | (3 - 1)]);
+            img.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+            let mut decoder = QoiReader::new(&img[..], 3, 4);
 
             let mut buf = vec![0u8; 12];
-            decoder.read(&mut buf).unwrap();
+            decoder.read_exact(&mut buf).unwrap();
             
-            assert!(buf == &[100, 100, 0, 100, 100, 0, 100, 100, 0, 100, 100, 0])
+            assert!(buf == [100, 100, 0, 100, 100, 0, 100, 100, 0, 100, 100, 0]);
+
+            //The end marker isn't mistaken for more pixels
+            assert_eq!(decoder.read(&mut buf).unwrap(), 0);
+            assert_eq!(decoder.end_marker_valid(), Some(true));
+        }
+
+        fn header(width: u32, height: u32, channels: u8, colour_space: u8) -> Vec<u8> {
+            let mut header = Vec::from(&b"qoif"[..]);
+            header.extend_from_slice(&width.to_be_bytes());
+            header.extend_from_slice(&height.to_be_bytes());
+            header.extend_from_slice(&[channels, colour_space]);
+            header
+        }
+
+        #[test]
+        fn test_invalid_headers() {
+            let truncated = header(1, 1, 3, 0);
+            let result = QoiDecoder::new(&truncated[..10]);
+            assert!(matches!(result, Err(QoiError::TruncatedData)));
+
+            let mut bad_magic = header(1, 1, 3, 0);
+            bad_magic[0] = b'p';
+            let result = QoiDecoder::new(&bad_magic[..]);
+            assert!(matches!(result, Err(QoiError::InvalidMagic(magic)) if &magic == b"poif"));
+
+            let bad_channels = header(1, 1, 5, 0);
+            let result = QoiDecoder::new(&bad_channels[..]);
+            assert!(matches!(result, Err(QoiError::InvalidChannels(5))));
+
+            let bad_colour_space = header(1, 1, 4, 2);
+            let result = QoiDecoder::new(&bad_colour_space[..]);
+            assert!(matches!(result, Err(QoiError::InvalidColorSpace(2))));
+        }
+
+        #[test]
+        fn test_limits() {
+            //Overflows a usize on both 32 and 64 bit targets
+            let huge = header(u32::MAX, u32::MAX, 4, 0);
+            let result = QoiDecoder::new(&huge[..]);
+            assert!(matches!(result, Err(QoiError::DimensionOverflow { .. })));
+
+            let big = header(1 << 15, 1 << 15, 4, 0);
+            let limits = Limits {
+                max_pixels: 1 << 20,
+                ..Limits::default()
+            };
+            let result = QoiDecoder::with_limits(&big[..], limits);
+            assert!(matches!(result, Err(QoiError::LimitsExceeded { .. })));
+
+            let wide = header(2048, 1, 3, 0);
+            let limits = Limits {
+                max_width: 1024,
+                ..Limits::default()
+            };
+            let result = QoiDecoder::with_limits(&wide[..], limits);
+            assert!(matches!(
+                result,
+                Err(QoiError::LimitsExceeded {
+                    width: 2048,
+                    height: 1
+                })
+            ));
+
+            let limits = Limits {
+                max_alloc: 2048 * 3 - 1,
+                ..Limits::default()
+            };
+            let result = QoiDecoder::with_limits(&wide[..], limits);
+            assert!(matches!(result, Err(QoiError::LimitsExceeded { .. })));
+
+            let limits = Limits {
+                max_width: 2048,
+                max_height: 1,
+                max_pixels: 2048,
+                max_alloc: 2048 * 3,
+            };
+            assert!(QoiDecoder::with_limits(&wide[..], limits).is_ok());
+        }
+
+        #[test]
+        fn test_truncated_chunks() {
+            //OP_RGBA is missing its alpha byte
+            let mut img = header(2, 1, 4, 0);
+            img.extend_from_slice(&[254, 1, 2, 3, 255, 1, 2, 3]);
+            let decoder = QoiDecoder::new(&img[..]).unwrap();
+
+            let mut bytes = vec![0u8; 8];
+            let result = decoder.read_image(&mut bytes);
+            assert!(matches!(result, Err(image::ImageError::IoError(_))));
+
+            //The QoiError can be recovered from the reader
+            let mut reader = QoiReader::new(&img[14..], 4, 2);
+            let e = QoiError::from(reader.read_exact(&mut bytes).unwrap_err());
+            assert!(matches!(e, QoiError::TruncatedData));
+        }
+
+        #[test]
+        fn test_color_space_round_trip() {
+            for &colour_space in &[ColorSpace::Srgb, ColorSpace::Linear] {
+                let mut img = Vec::new();
+                QoiEncoder::new(&mut img)
+                    .with_color_space(colour_space)
+                    .write_image(&[1, 2, 3], 1, 1, image::ColorType::Rgb8)
+                    .unwrap();
+                assert_eq!(img[13], u8::from(colour_space));
+
+                let decoder = QoiDecoder::new(&img[..]).unwrap();
+                assert_eq!(decoder.color_space(), colour_space);
+            }
+        }
+
+        fn encoded_image() -> Vec<u8> {
+            let mut img = Vec::new();
+            QoiEncoder::new(&mut img)
+                .write_image(&[1, 2, 3, 1, 2, 3, 4, 5, 6], 3, 1, image::ColorType::Rgb8)
+                .unwrap();
+            img
+        }
+
+        #[test]
+        fn test_empty_image_decoder() {
+            use crate::header::{ColorSpace, Header};
+            use crate::ops::assemble;
+
+            for &(width, height) in &[(0, 5), (5, 0), (0, 0)] {
+                let header = Header {
+                    width,
+                    height,
+                    channels: 4,
+                    color_space: ColorSpace::Srgb,
+                };
+                let img = assemble(&header, []).unwrap();
+                let image =
+                    image::DynamicImage::from_decoder(QoiDecoder::new(&img[..]).unwrap()).unwrap();
+                assert_eq!(image.dimensions(), (width, height));
+                QoiDecoder::new(&img[..])
+                    .unwrap()
+                    .read_image(&mut [])
+                    .unwrap();
+            }
+        }
+
+        #[test]
+        fn test_end_marker() {
+            let img = encoded_image();
+            let mut bytes = vec![0u8; 9];
+            let footer = QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_image_with_footer(&mut bytes)
+                .unwrap();
+
+            assert_eq!(bytes, [1, 2, 3, 1, 2, 3, 4, 5, 6]);
+            assert!(footer.end_marker_valid);
+            assert!(footer.trailing_data.is_empty());
+        }
+
+        #[test]
+        fn test_trailing_data() {
+            let mut img = encoded_image();
+            img.extend_from_slice(b"trailing");
+
+            let mut bytes = vec![0u8; 9];
+            let footer = QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_image_with_footer(&mut bytes)
+                .unwrap();
+            assert!(footer.end_marker_valid);
+            assert_eq!(footer.trailing_data, b"trailing");
+
+            //read_image stops after the last pixel even though there's more data
+            let mut bytes = vec![0u8; 9];
+            QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_image(&mut bytes)
+                .unwrap();
+            assert_eq!(bytes, [1, 2, 3, 1, 2, 3, 4, 5, 6]);
+        }
+
+        #[test]
+        fn test_garbled_end_marker() {
+            let mut img = encoded_image();
+            let len = img.len();
+            img[len - 1] = 2;
+
+            let mut bytes = vec![0u8; 9];
+            let result = QoiDecoder::new(&img[..]).unwrap().read_image(&mut bytes);
+            assert!(result.is_err());
+
+            let footer = QoiDecoder::new(&img[..])
+                .unwrap()
+                .with_end_marker_mode(EndMarkerMode::Lenient)
+                .read_image_with_footer(&mut bytes)
+                .unwrap();
+            assert_eq!(bytes, [1, 2, 3, 1, 2, 3, 4, 5, 6]);
+            assert!(!footer.end_marker_valid);
+
+            //Missing entirely
+            let result = QoiDecoder::new(&img[..(len - 8)])
+                .unwrap()
+                .read_image_with_footer(&mut bytes);
+            assert!(matches!(result, Err(QoiError::MissingEndMarker)));
+        }
+
+        #[test]
+        fn test_run_past_last_pixel() {
+            //OP_RGB followed by a run of 4 in a 3 pixel image
+            let mut img = header(3, 1, 3, 0);
+            img.extend_from_slice(&[254, 1, 2, 3, 0b1100_0000 | (4 - 1)]);
+            img.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+
+            let mut bytes = vec![0u8; 9];
+            let footer = QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_image_with_footer(&mut bytes)
+                .unwrap();
+            assert_eq!(bytes, [1, 2, 3, 1, 2, 3, 1, 2, 3]);
+            assert!(footer.end_marker_valid);
+        }
+
+        #[test]
+        fn test_decode_from_slice() {
+            for &(channels, color_type) in
+                &[(3, image::ColorType::Rgb8), (4, image::ColorType::Rgba8)]
+            {
+                let bytes = synthetic_image(channels, 5000);
+                let mut img = Vec::new();
+                QoiEncoder::new(&mut img)
+                    .write_image(&bytes, 100, 50, color_type)
+                    .unwrap();
+
+                let (header, decoded) = decode_from_slice(&img).unwrap();
+                assert_eq!((header.width, header.height), (100, 50));
+                assert_eq!(header.channels, channels as u8);
+                assert_eq!(decoded, bytes);
+
+                let mut read = vec![0u8; bytes.len()];
+                QoiDecoder::new(&img[..])
+                    .unwrap()
+                    .read_image(&mut read)
+                    .unwrap();
+                assert_eq!(read, bytes);
+            }
+        }
+
+        #[test]
+        fn test_decode_from_slice_errors() {
+            let img = encoded_image();
+            for len in 0..(img.len() - 1) {
+                assert!(decode_from_slice(&img[..len]).is_err());
+            }
+
+            let mut buf = vec![0u8; 8];
+            let result = decode_into(&img, &mut buf);
+            assert!(matches!(
+                result,
+                Err(QoiError::BufferSizeMismatch {
+                    expected: 9,
+                    actual: 8
+                })
+            ));
+
+            //Claims far more pixels than the data could hold
+            let mut huge = header(1 << 16, 1 << 16, 4, 0);
+            huge.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+            assert!(matches!(
+                decode_from_slice(&huge),
+                Err(QoiError::TruncatedData)
+            ));
+        }
+
+        #[test]
+        fn test_index_after_initial_run() {
+            //The starting pixel is only ever seen through a run, but it can still be indexed
+            let bytes = [0, 0, 0, 255, 0, 0, 0, 255, 10, 10, 10, 255, 0, 0, 0, 255];
+            let mut img = Vec::new();
+            QoiEncoder::new(&mut img)
+                .write_image(&bytes, 4, 1, image::ColorType::Rgba8)
+                .unwrap();
+
+            let (_, decoded) = decode_from_slice(&img).unwrap();
+            assert_eq!(decoded, bytes);
+        }
+
+        #[test]
+        fn test_read_rows() {
+            let bytes = synthetic_image(3, 5000);
+            let mut img = Vec::new();
+            QoiEncoder::new(&mut img)
+                .write_image(&bytes, 50, 100, image::ColorType::Rgb8)
+                .unwrap();
+
+            let mut row = vec![0u8; 150];
+            let mut rows = Vec::new();
+            let footer = QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_rows(&mut row, |y, row| {
+                    assert_eq!(y as usize, rows.len() / 150);
+                    rows.extend_from_slice(row);
+                })
+                .unwrap();
+            assert!(footer.end_marker_valid);
+            assert_eq!(rows, bytes);
+
+            let mut short_row = vec![0u8; 149];
+            let result = QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_rows(&mut short_row, |_, _| {});
+            assert!(matches!(result, Err(QoiError::BufferSizeMismatch { .. })));
+        }
+
+        #[test]
+        fn test_run_across_rows() {
+            //One run covers the end of the first row, all of the second and the start of the third
+            let mut bytes = vec![9, 9, 9];
+            bytes.extend_from_slice(&[1, 2, 3].repeat(6));
+            bytes.extend_from_slice(&[9, 9, 9]);
+            let mut img = Vec::new();
+            QoiEncoder::new(&mut img)
+                .write_image(&bytes, 2, 4, image::ColorType::Rgb8)
+                .unwrap();
+
+            let mut row = [0u8; 6];
+            let mut rows = Vec::new();
+            QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_rows(&mut row, |y, row| rows.push((y, row.to_vec())))
+                .unwrap();
+            assert_eq!(rows.len(), 4);
+            let decoded: Vec<u8> = rows.into_iter().flat_map(|(_, row)| row).collect();
+            assert_eq!(decoded, bytes);
+
+            //A run never writes past the end of the caller's buffer
+            let mut reader = QoiDecoder::new(&img[..]).unwrap().into_reader().unwrap();
+            let mut buf = [0u8; 6];
+            assert_eq!(reader.read(&mut buf).unwrap(), 6);
+            assert_eq!(buf, bytes[..6]);
+        }
+
+        #[test]
+        fn test_small_reads() {
+            let (bytes, img) = encoded_fixture();
+
+            //Buffer sizes which split pixels, and which are smaller than a pixel
+            for &size in &[1, 3, 5, 7, 4096] {
+                let mut reader = QoiDecoder::new(&img[..]).unwrap().into_reader().unwrap();
+                let mut decoded = Vec::new();
+                let mut buf = vec![0u8; size];
+                loop {
+                    let read = reader.read(&mut buf).unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    decoded.extend_from_slice(&buf[..read]);
+                }
+                assert_eq!(decoded, bytes);
+                assert_eq!(reader.end_marker_valid(), Some(true));
+            }
+
+            let mut reader = QoiDecoder::new(&img[..]).unwrap().into_reader().unwrap();
+            let mut copied = Vec::new();
+            std::io::copy(&mut reader, &mut copied).unwrap();
+            assert_eq!(copied, bytes);
+        }
+
+        fn decode_with_layout(img: &[u8], layout: PixelLayout) -> Vec<u8> {
+            let decoder = QoiDecoder::new(img).unwrap().with_layout(layout);
+            let mut bytes = vec![0u8; decoder.total_bytes() as usize];
+            decoder.read_image_with_footer(&mut bytes).unwrap();
+            bytes
+        }
+
+        #[test]
+        fn test_pixel_layouts() {
+            let rgba = [10, 20, 30, 40, 200, 100, 50, 255];
+            let mut img = Vec::new();
+            QoiEncoder::new(&mut img)
+                .write_image(&rgba, 2, 1, image::ColorType::Rgba8)
+                .unwrap();
+
+            assert_eq!(decode_with_layout(&img, PixelLayout::Native), rgba);
+            assert_eq!(
+                decode_with_layout(&img, PixelLayout::Rgb),
+                [10, 20, 30, 200, 100, 50]
+            );
+            assert_eq!(
+                decode_with_layout(&img, PixelLayout::Bgra),
+                [30, 20, 10, 40, 50, 100, 200, 255]
+            );
+            assert_eq!(
+                decode_with_layout(&img, PixelLayout::Argb),
+                [40, 10, 20, 30, 255, 200, 100, 50]
+            );
+
+            //Grayscale matches image's own conversion
+            let reference = image::DynamicImage::ImageRgba8(
+                image::RgbaImage::from_raw(2, 1, rgba.to_vec()).unwrap(),
+            );
+            assert_eq!(
+                decode_with_layout(&img, PixelLayout::Luma),
+                reference.to_luma8().into_raw()
+            );
+            assert_eq!(
+                decode_with_layout(&img, PixelLayout::LumaAlpha),
+                reference.to_luma_alpha8().into_raw()
+            );
+
+            //RGB images are given an opaque alpha
+            let mut img = Vec::new();
+            QoiEncoder::new(&mut img)
+                .write_image(&rgba[..6], 2, 1, image::ColorType::Rgb8)
+                .unwrap();
+            assert_eq!(
+                decode_with_layout(&img, PixelLayout::Rgba),
+                [10, 20, 30, 255, 40, 200, 100, 255]
+            );
+
+            let decoder = QoiDecoder::new(&img[..])
+                .unwrap()
+                .with_layout(PixelLayout::Bgra);
+            assert_eq!(decoder.color_type(), image::ColorType::Bgra8);
+
+            //image would read ARGB as RGBA
+            let argb = QoiDecoder::new(&img[..])
+                .unwrap()
+                .with_layout(PixelLayout::Argb);
+            assert!(matches!(
+                image::DynamicImage::from_decoder(argb),
+                Err(image::ImageError::Unsupported(_))
+            ));
+        }
+
+        #[test]
+        fn test_layout_small_reads() {
+            let bytes = synthetic_image(4, 500);
+            let mut img = Vec::new();
+            QoiEncoder::new(&mut img)
+                .write_image(&bytes, 50, 10, image::ColorType::Rgba8)
+                .unwrap();
+            let expected: Vec<u8> = bytes
+                .chunks(4)
+                .flat_map(|p| [p[2], p[1], p[0], p[3]])
+                .collect();
+
+            let mut reader = QoiDecoder::new(&img[..])
+                .unwrap()
+                .with_layout(PixelLayout::Bgra)
+                .into_reader()
+                .unwrap();
+            let mut decoded = Vec::new();
+            let mut buf = [0u8; 3];
+            loop {
+                let read = reader.read(&mut buf).unwrap();
+                if read == 0 {
+                    break;
+                }
+                decoded.extend_from_slice(&buf[..read]);
+            }
+            assert_eq!(decoded, expected);
+
+            let mut rows = Vec::new();
+            QoiDecoder::new(&img[..])
+                .unwrap()
+                .with_layout(PixelLayout::Rgb)
+                .read_rows(&mut [0u8; 150], |_, row| rows.extend_from_slice(row))
+                .unwrap();
+            let rgb: Vec<u8> = bytes.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+            assert_eq!(rows, rgb);
+        }
+
+        #[test]
+        fn test_strided_decode() {
+            let bytes = synthetic_image(4, 500);
+            let mut img = Vec::new();
+            QoiEncoder::new(&mut img)
+                .write_image(&bytes, 25, 20, image::ColorType::Rgba8)
+                .unwrap();
+
+            //Decode into the middle of a 40x30 canvas, leaving everything else alone
+            let stride = 40 * 4;
+            let mut canvas = vec![0xAA; stride * 30];
+            let start = 5 * stride + 10 * 4;
+            QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_image_strided(&mut canvas[start..], stride)
+                .unwrap();
+            for (y, row) in canvas.chunks(stride).enumerate() {
+                for (x, pixel) in row.chunks(4).enumerate() {
+                    if (10..35).contains(&x) && (5..25).contains(&y) {
+                        let i = ((y - 5) * 25 + x - 10) * 4;
+                        assert_eq!(pixel, &bytes[i..i + 4]);
+                    } else {
+                        assert_eq!(pixel, [0xAA; 4]);
+                    }
+                }
+            }
+
+            //The last row doesn't need padding
+            let mut exact = vec![0; 19 * stride + 25 * 3];
+            QoiDecoder::new(&img[..])
+                .unwrap()
+                .with_layout(PixelLayout::Rgb)
+                .read_image_strided(&mut exact, stride)
+                .unwrap();
+            let last_row: Vec<u8> = bytes[19 * 100..]
+                .chunks(4)
+                .flat_map(|p| [p[0], p[1], p[2]])
+                .collect();
+            assert_eq!(&exact[19 * stride..], &last_row[..]);
+
+            let too_small = QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_image_strided(&mut exact, stride);
+            assert!(matches!(
+                too_small,
+                Err(QoiError::BufferSizeMismatch { .. })
+            ));
+            let narrow = QoiDecoder::new(&img[..])
+                .unwrap()
+                .read_image_strided(&mut canvas, 99);
+            assert!(matches!(
+                narrow,
+                Err(QoiError::InvalidStride {
+                    stride: 99,
+                    row_size: 100
+                })
+            ));
+        }
+
+        #[test]
+        fn test_incremental_decoder() {
+            let (bytes, img) = encoded_fixture();
+
+            //Small feeds split the header, chunks and end marker across calls
+            for &size in &[1, 2, 3, 7, 64, img.len()] {
+                let mut decoder = QoiIncrementalDecoder::new();
+                let mut available = 0;
+                for data in img.chunks(size) {
+                    let pixels = decoder.feed(data).unwrap();
+                    assert!(pixels >= available);
+                    available = pixels;
+                    assert_eq!(decoder.rows_available(), available / 100);
+                    assert_eq!(decoder.pixels()[..available * 4], bytes[..available * 4]);
+                }
+                assert!(decoder.is_finished());
+                assert_eq!(decoder.header().unwrap().width, 100);
+                assert_eq!(decoder.into_pixels(), bytes);
+            }
+        }
+
+        #[test]
+        fn test_incremental_decoder_large_header() {
+            use crate::header::{ColorSpace, Header};
+
+            let header = Header {
+                width: 65535,
+                height: 65535,
+                channels: 4,
+                color_space: ColorSpace::Srgb,
+            };
+            let mut decoder = QoiIncrementalDecoder::new();
+            assert_eq!(decoder.feed(&header.to_bytes()).unwrap(), 0);
+            //Nothing is allocated for pixels which haven't arrived
+            assert!(decoder.pixels().is_empty());
+
+            //A run of 62
+            assert_eq!(decoder.feed(&[0xFD]).unwrap(), 62);
+            assert_eq!(decoder.pixels(), &[0, 0, 0, 255].repeat(62)[..]);
+        }
+
+        #[test]
+        fn test_incremental_decoder_errors() {
+            let img = encoded_image();
+
+            //Missing data isn't an error until it arrives
+            let mut decoder = QoiIncrementalDecoder::new();
+            assert_eq!(decoder.feed(&img[..img.len() - 1]).unwrap(), 3);
+            assert!(decoder.header().is_some());
+            assert!(!decoder.is_finished());
+
+            let mut garbled = img.clone();
+            *garbled.last_mut().unwrap() = 2;
+            let result = QoiIncrementalDecoder::new().feed(&garbled);
+            assert!(matches!(result, Err(QoiError::MissingEndMarker)));
+
+            let limits = Limits {
+                max_pixels: 2,
+                ..Limits::default()
+            };
+            let result = QoiIncrementalDecoder::with_limits(limits).feed(&img);
+            assert!(matches!(result, Err(QoiError::LimitsExceeded { .. })));
         }
 
         #[test]
@@ -129,9 +1128,8 @@ mod tests {
                     std::fs::read("qoi_test_images/".to_owned() + &fname + ".qoi").unwrap();
                 let decoder = QoiDecoder::new(&reader[..]).unwrap();
 
-                let (w, h) = decoder.dimensions();
-                let channels = decoder.color_type().channel_count();
-                let mut bytes: Vec<u8> = vec![0; (w * h * channels as u32) as usize];
+                //QoiDecoder has already checked this fits in a usize
+                let mut bytes: Vec<u8> = vec![0; decoder.total_bytes() as usize];
 
                 decoder.read_image(&mut bytes).unwrap();
 
@@ -148,10 +1146,17 @@ mod tests {
 
     #[cfg(test)]
     mod general_tests {
-
+        use super::{encoded_fixture, synthetic_image};
         use crate::{
             chunks::{OP_DIFF, OP_LUMA},
+            decoder::decode_from_slice,
+            diff::{diff, diff_mask},
+            error::QoiError,
+            header::{ColorSpace, Header},
+            info::{heat_map, inspect},
+            ops::{assemble, Op, QoiTokenizer},
             util::Pixel,
+            verify::{verify, Canonical},
         };
 
         #[test]
@@ -189,5 +1194,387 @@ mod tests {
                 assert_eq!(Pixel::from((base_pixel, chunk)), test_pixel);
             }
         }
+
+        #[test]
+        fn test_tokenizer_round_trip() {
+            let (bytes, img) = encoded_fixture();
+
+            let mut tokenizer = QoiTokenizer::new(&img).unwrap();
+            let header = tokenizer.header();
+            let tokens = tokenizer.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(tokenizer.offset(), img.len() - 8);
+
+            //Every token's colour matches the pixels it covers
+            let mut next_pixel = 0;
+            for token in &tokens {
+                assert_eq!(token.pixel, next_pixel);
+                for i in token.pixel..token.pixel + token.count {
+                    let i = i as usize * 4;
+                    assert_eq!(bytes[i..i + 4], token.color);
+                }
+                next_pixel += token.count;
+            }
+            assert_eq!(next_pixel, 5000);
+
+            let ops = tokens.iter().map(|token| token.op);
+            assert_eq!(assemble(&header, ops).unwrap(), img);
+        }
+
+        #[test]
+        fn test_assembler() {
+            let header = Header {
+                width: 5,
+                height: 1,
+                channels: 3,
+                color_space: ColorSpace::Srgb,
+            };
+            let ops = vec![
+                Op::Rgb {
+                    r: 10,
+                    g: 20,
+                    b: 30,
+                },
+                Op::Diff {
+                    dr: -2,
+                    dg: 1,
+                    db: 0,
+                },
+                Op::Luma {
+                    dg: -32,
+                    dr_dg: 7,
+                    db_dg: -8,
+                },
+                Op::Index(Pixel::new(10, 20, 30, 255).hash() as u8),
+                Op::Run(1),
+            ];
+            let img = assemble(&header, ops).unwrap();
+            let (_, decoded) = decode_from_slice(&img).unwrap();
+            assert_eq!(
+                decoded,
+                [10, 20, 30, 8, 21, 30, 239, 245, 246, 10, 20, 30, 10, 20, 30]
+            );
+
+            for &op in &[
+                Op::Run(0),
+                Op::Index(64),
+                Op::Diff {
+                    dr: 2,
+                    dg: 0,
+                    db: 0,
+                },
+            ] {
+                assert!(matches!(
+                    assemble(&header, vec![op]),
+                    Err(QoiError::InvalidOp(_))
+                ));
+            }
+        }
+
+        #[test]
+        fn test_inspect() {
+            let header = Header {
+                width: 10,
+                height: 1,
+                channels: 3,
+                color_space: ColorSpace::Linear,
+            };
+            let ops = vec![
+                Op::Rgb {
+                    r: 10,
+                    g: 20,
+                    b: 30,
+                },
+                Op::Run(3),
+                Op::Diff {
+                    dr: 1,
+                    dg: 1,
+                    db: 1,
+                },
+                Op::Index(Pixel::new(10, 20, 30, 255).hash() as u8),
+                Op::Run(3),
+                Op::Luma {
+                    dg: 5,
+                    dr_dg: 0,
+                    db_dg: 0,
+                },
+            ];
+            let img = assemble(&header, ops).unwrap();
+            let info = inspect(&img).unwrap();
+
+            assert_eq!(info.header, header);
+            assert_eq!(info.file_size, img.len());
+            assert_eq!(info.ops.total(), 6);
+            assert_eq!((info.ops.rgb, info.ops.diff, info.ops.luma), (1, 1, 1));
+            assert_eq!((info.ops.index, info.ops.run, info.ops.rgba), (1, 2, 0));
+            assert_eq!(info.run_lengths[2], 2);
+            assert_eq!(info.index_hit_rate(), Some(0.25));
+            assert!(info.end_marker_valid);
+            assert_eq!(info.compression_ratio(), 30.0 / img.len() as f64);
+            assert_eq!(info.bits_per_pixel(), Some(img.len() as f64 * 8.0 / 10.0));
+
+            //Stats with nothing to divide by
+            let runs = inspect(&assemble(&header, [Op::Run(10)]).unwrap()).unwrap();
+            assert_eq!(runs.index_hit_rate(), None);
+            let empty = Header {
+                width: 0,
+                height: 0,
+                ..header
+            };
+            let empty = inspect(&assemble(&empty, []).unwrap()).unwrap();
+            assert_eq!(empty.bits_per_pixel(), None);
+            assert_eq!(empty.index_hit_rate(), None);
+            assert_eq!(empty.compression_ratio(), 0.0);
+        }
+
+        #[test]
+        fn test_heat_map() {
+            let header = Header {
+                width: 3,
+                height: 2,
+                channels: 4,
+                color_space: ColorSpace::Srgb,
+            };
+            let ops = vec![
+                Op::Rgba {
+                    r: 1,
+                    g: 2,
+                    b: 3,
+                    a: 4,
+                },
+                Op::Run(3),
+                Op::Diff {
+                    dr: 0,
+                    dg: 0,
+                    db: 1,
+                },
+                Op::Index(Pixel::new(1, 2, 3, 4).hash() as u8),
+            ];
+            let img = assemble(&header, ops).unwrap();
+            let map = heat_map(&img).unwrap();
+
+            assert_eq!(map.dimensions(), (3, 2));
+            let colours: Vec<_> = map.pixels().collect();
+            assert_ne!(colours[0], colours[1]);
+            //The run wraps onto the second row
+            assert_eq!(colours[1], colours[3]);
+            assert_ne!(colours[3], colours[4]);
+            assert_ne!(colours[4], colours[5]);
+            assert_ne!(colours[0], colours[5]);
+
+            let mut huge = Header {
+                width: 1 << 16,
+                height: 1 << 16,
+                ..header
+            }
+            .to_bytes()
+            .to_vec();
+            huge.push(0b1111_1101);
+            assert!(matches!(heat_map(&huge), Err(QoiError::TruncatedData)));
+        }
+
+        #[test]
+        fn test_verify() {
+            let (_, img) = encoded_fixture();
+            let report = verify(&img);
+            assert!(report.is_valid());
+            assert!(report.suboptimal.is_empty());
+
+            let header = Header {
+                width: 7,
+                height: 1,
+                channels: 4,
+                color_space: ColorSpace::Srgb,
+            };
+            let ops = vec![
+                Op::Rgba {
+                    r: 1,
+                    g: 2,
+                    b: 3,
+                    a: 255,
+                },
+                Op::Rgb { r: 1, g: 2, b: 4 },
+                Op::Rgb { r: 1, g: 2, b: 3 },
+                Op::Run(1),
+                Op::Run(2),
+                Op::Rgb { r: 1, g: 2, b: 3 },
+            ];
+            let mut img = assemble(&header, ops).unwrap();
+            img.push(0);
+            let report = verify(&img);
+            assert!(report.errors.is_empty());
+            assert_eq!(report.trailing_data, 1);
+
+            let canonical: Vec<_> = report
+                .suboptimal
+                .iter()
+                .map(|suboptimal| (suboptimal.pixel, suboptimal.canonical))
+                .collect();
+            assert_eq!(
+                canonical,
+                [
+                    (
+                        0,
+                        Canonical::Op(Op::Luma {
+                            dg: 2,
+                            dr_dg: -1,
+                            db_dg: 1
+                        })
+                    ),
+                    (
+                        1,
+                        Canonical::Op(Op::Diff {
+                            dr: 0,
+                            dg: 0,
+                            db: 1
+                        })
+                    ),
+                    (
+                        2,
+                        Canonical::Op(Op::Index(Pixel::new(1, 2, 3, 255).hash() as u8))
+                    ),
+                    (4, Canonical::ExtendRun),
+                    (6, Canonical::ExtendRun),
+                ]
+            );
+
+            //qoi.h doesn't index the opening run of black, so it can't come back as an index
+            let header = Header {
+                width: 4,
+                height: 1,
+                channels: 3,
+                color_space: ColorSpace::Srgb,
+            };
+            let black = Pixel::new(0, 0, 0, 255).hash() as u8;
+            let grey = Op::Rgb {
+                r: 100,
+                g: 100,
+                b: 100,
+            };
+            let ops = vec![Op::Run(2), grey, Op::Rgb { r: 0, g: 0, b: 0 }];
+            assert!(verify(&assemble(&header, ops).unwrap())
+                .suboptimal
+                .is_empty());
+
+            let img = assemble(&header, vec![Op::Run(2), grey, Op::Index(black)]).unwrap();
+            let report = verify(&img);
+            assert!(report.is_valid());
+            assert_eq!(report.suboptimal.len(), 1);
+            assert_eq!(
+                report.suboptimal[0].canonical,
+                Canonical::Op(Op::Rgb { r: 0, g: 0, b: 0 })
+            );
+        }
+
+        #[test]
+        fn test_verify_errors() {
+            assert!(matches!(
+                verify(b"qoif").errors.as_slice(),
+                [QoiError::TruncatedData]
+            ));
+
+            let header = Header {
+                width: 4,
+                height: 1,
+                channels: 3,
+                color_space: ColorSpace::Srgb,
+            };
+            let too_many = assemble(&header, vec![Op::Run(5)]).unwrap();
+            assert!(matches!(
+                verify(&too_many).errors.as_slice(),
+                [QoiError::PixelCountMismatch {
+                    expected: 4,
+                    actual: 5
+                }]
+            ));
+
+            let too_few = assemble(&header, vec![Op::Run(2)]).unwrap();
+            assert!(matches!(
+                verify(&too_few).errors.as_slice(),
+                [QoiError::PixelCountMismatch {
+                    expected: 4,
+                    actual: 2
+                }]
+            ));
+
+            let mut no_marker = assemble(&header, vec![Op::Run(4)]).unwrap();
+            no_marker.truncate(no_marker.len() - 8);
+            no_marker.extend_from_slice(&[1, 2, 3]);
+            assert!(matches!(
+                verify(&no_marker).errors.as_slice(),
+                [QoiError::MissingEndMarker]
+            ));
+        }
+
+        #[test]
+        fn test_diff() {
+            let a = image::RgbaImage::from_raw(2, 2, synthetic_image(4, 4)).unwrap();
+            let mut b = a.clone();
+
+            let same = diff(&a, &b).unwrap();
+            assert!(same.is_identical());
+            assert_eq!(same.psnr, f64::INFINITY);
+
+            let empty = image::RgbaImage::new(0, 0);
+            let empty = diff(&empty, &empty).unwrap();
+            assert!(empty.is_identical());
+            assert_eq!(empty.psnr, f64::INFINITY);
+
+            b.get_pixel_mut(1, 0).0[2] ^= 0b1000;
+            b.get_pixel_mut(0, 1).0[3] ^= 0b10;
+            let different = diff(&a, &b).unwrap();
+            assert_eq!(different.differing_pixels, 2);
+            assert_eq!(different.max_delta, [0, 0, 8, 2]);
+            let mse = (8.0 * 8.0 + 2.0 * 2.0) / 16.0;
+            assert_eq!(different.psnr, 10.0 * (255.0f64 * 255.0 / mse).log10());
+
+            let mask = diff_mask(&a, &b).unwrap();
+            assert_eq!(mask.as_raw(), &[0, 255, 255, 0]);
+
+            let small = image::RgbaImage::new(1, 2);
+            assert!(matches!(
+                diff(&a, &small),
+                Err(QoiError::DimensionMismatch {
+                    expected: (2, 2),
+                    actual: (1, 2)
+                })
+            ));
+        }
+
+        #[test]
+        fn test_dynamic_image_helpers() {
+            let rgba = image::RgbaImage::from_raw(50, 20, synthetic_image(4, 1000)).unwrap();
+            let image = image::DynamicImage::ImageRgba8(rgba);
+
+            let mut qoi = Vec::new();
+            crate::write_to(&image, &mut qoi).unwrap();
+            assert!(crate::is_qoi(&qoi));
+            assert_eq!(crate::load_from_memory(&qoi).unwrap(), image);
+
+            //Other formats still go through image
+            let mut png = Vec::new();
+            image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+            assert!(!crate::is_qoi(&png));
+            assert_eq!(crate::load_from_memory(&png).unwrap(), image);
+
+            let dir = std::env::temp_dir().join(format!("rust-qoi-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("image.QOI");
+            crate::save(&image, &path).unwrap();
+            assert_eq!(std::fs::read(&path).unwrap(), qoi);
+            assert_eq!(crate::open(&path).unwrap(), image);
+
+            //Grayscale doesn't exist in QOI, so it's stored as RGB
+            let grey = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(3, 2, |x, y| {
+                image::Luma([(x * 50 + y) as u8])
+            }));
+            let path = dir.join("grey.qoi");
+            crate::save(&grey, &path).unwrap();
+            assert_eq!(
+                crate::open(&path).unwrap(),
+                image::DynamicImage::ImageRgb8(grey.to_rgb8())
+            );
+
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
     }
 }
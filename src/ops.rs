@@ -0,0 +1,228 @@
+use std::io::Write;
+
+use crate::chunks::{QoiChunk, OP_DIFF, OP_INDEX, OP_LUMA, OP_RGB, OP_RGBA, OP_RUN};
+use crate::codec::QoiCodecState;
+use crate::consts::{END_MARKER, MAX_RUN_LENGTH, SEEN_PIXEL_ARRAY_SIZE};
+use crate::error::QoiError;
+use crate::header::{Header, HEADER_SIZE};
+use crate::util::Pixel;
+
+/// A single QOI opcode. Differences are stored without their bias, so `Diff { dr: -2, .. }`
+/// means the red channel went down by 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// Sets the colour channels and keeps the previous alpha.
+    Rgb {
+        r: u8,
+        g: u8,
+        b: u8,
+    },
+    Rgba {
+        r: u8,
+        g: u8,
+        b: u8,
+        a: u8,
+    },
+    /// Repeats the pixel in this slot of the index, in `0..64`.
+    Index(u8),
+    /// Every difference is in `-2..=1`.
+    Diff {
+        dr: i8,
+        dg: i8,
+        db: i8,
+    },
+    /// `dg` is in `-32..=31`, the other differences are relative to `dg` and in `-8..=7`.
+    Luma {
+        dg: i8,
+        dr_dg: i8,
+        db_dg: i8,
+    },
+    /// Repeats the previous pixel, in `1..=62`.
+    Run(u8),
+}
+
+impl Op {
+    /// The op's name in the spec, e.g. `OP_RGB`.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Rgb { .. } => "OP_RGB",
+            Op::Rgba { .. } => "OP_RGBA",
+            Op::Index(_) => "OP_INDEX",
+            Op::Diff { .. } => "OP_DIFF",
+            Op::Luma { .. } => "OP_LUMA",
+            Op::Run(_) => "OP_RUN",
+        }
+    }
+
+    /// Writes the op's bytes to w.
+    pub fn encode<W: Write>(&self, w: &mut W) -> Result<(), QoiError> {
+        self.to_chunk()?.encode(w)?;
+        Ok(())
+    }
+
+    fn to_chunk(self) -> Result<QoiChunk, QoiError> {
+        let in_range = match self {
+            Op::Rgb { .. } | Op::Rgba { .. } => true,
+            Op::Index(index) => (index as usize) < SEEN_PIXEL_ARRAY_SIZE,
+            Op::Diff { dr, dg, db } => [dr, dg, db].iter().all(|d| (-2..=1).contains(d)),
+            Op::Luma { dg, dr_dg, db_dg } => {
+                (-32..=31).contains(&dg) && (-8..=7).contains(&dr_dg) && (-8..=7).contains(&db_dg)
+            }
+            Op::Run(run) => (1..=MAX_RUN_LENGTH).contains(&run),
+        };
+        if !in_range {
+            return Err(QoiError::InvalidOp(self));
+        }
+
+        //The stored values are biased so they're never negative
+        let bias = |d: i8, bias: i8| (d + bias) as u8;
+        Ok(match self {
+            Op::Rgb { r, g, b } => QoiChunk::RGB(OP_RGB::new(Pixel::new(r, g, b, 0), 0)),
+            Op::Rgba { r, g, b, a } => QoiChunk::RGBA(OP_RGBA { r, g, b, a }),
+            Op::Index(index) => QoiChunk::INDEX(OP_INDEX { index }),
+            Op::Diff { dr, dg, db } => QoiChunk::DIFF(OP_DIFF {
+                dr: bias(dr, 2),
+                dg: bias(dg, 2),
+                db: bias(db, 2),
+            }),
+            Op::Luma { dg, dr_dg, db_dg } => QoiChunk::LUMA(OP_LUMA {
+                dg: bias(dg, 32),
+                dr_dg: bias(dr_dg, 8),
+                db_dg: bias(db_dg, 8),
+            }),
+            Op::Run(run) => QoiChunk::RUN(OP_RUN::new(run)),
+        })
+    }
+}
+
+impl From<&QoiChunk> for Op {
+    fn from(chunk: &QoiChunk) -> Op {
+        let unbias = |d: u8, bias: i8| d as i8 - bias;
+        match chunk {
+            QoiChunk::RGB(chunk) => Op::Rgb {
+                r: chunk.r,
+                g: chunk.g,
+                b: chunk.b,
+            },
+            QoiChunk::RGBA(chunk) => Op::Rgba {
+                r: chunk.r,
+                g: chunk.g,
+                b: chunk.b,
+                a: chunk.a,
+            },
+            QoiChunk::INDEX(chunk) => Op::Index(chunk.index),
+            QoiChunk::DIFF(chunk) => Op::Diff {
+                dr: unbias(chunk.dr, 2),
+                dg: unbias(chunk.dg, 2),
+                db: unbias(chunk.db, 2),
+            },
+            QoiChunk::LUMA(chunk) => Op::Luma {
+                dg: unbias(chunk.dg, 32),
+                dr_dg: unbias(chunk.dr_dg, 8),
+                db_dg: unbias(chunk.db_dg, 8),
+            },
+            QoiChunk::RUN(chunk) => Op::Run(chunk.run_length()),
+        }
+    }
+}
+
+/// An op along with where it was found and which pixels it produced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub op: Op,
+    /// Byte offset of the op from the start of the file.
+    pub offset: usize,
+    /// Index of the first pixel the op produced, pixels are numbered in row major order.
+    pub pixel: u64,
+    /// How many pixels the op produced, this is only more than 1 for runs.
+    pub count: u64,
+    /// The RGBA colour of the pixels the op produced.
+    pub color: [u8; 4],
+}
+
+/// Splits a QOI file into ops, stopping once every pixel in the header has been produced.
+pub struct QoiTokenizer<'a> {
+    data: &'a [u8],
+    header: Header,
+    offset: usize,
+    pixel: u64,
+    state: QoiCodecState,
+    failed: bool,
+}
+
+impl<'a> QoiTokenizer<'a> {
+    pub fn new(data: &'a [u8]) -> Result<QoiTokenizer<'a>, QoiError> {
+        Ok(QoiTokenizer {
+            data,
+            header: Header::from_bytes(data)?,
+            offset: HEADER_SIZE,
+            pixel: 0,
+            state: QoiCodecState::new(),
+            failed: false,
+        })
+    }
+
+    #[must_use]
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    /// Byte offset of the next op, once every op has been read this is where the end marker
+    /// should be.
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl Iterator for QoiTokenizer<'_> {
+    type Item = Result<Token, QoiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.pixel >= self.header.pixels() {
+            return None;
+        }
+
+        let (chunk, size) = match QoiChunk::decode_slice(&self.data[self.offset..], &self.state) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+        let op = Op::from(&chunk);
+        let (pixel, count) = self.state.process_chunk(chunk);
+
+        let token = Token {
+            op,
+            offset: self.offset,
+            pixel: self.pixel,
+            count: count as u64,
+            color: [pixel.r(), pixel.g(), pixel.b(), pixel.a()],
+        };
+        self.offset += size;
+        self.pixel += count as u64;
+        Some(Ok(token))
+    }
+}
+
+/// Writes ops to w as they are, without checking they produce the right number of pixels.
+pub fn write_ops<W, I>(w: &mut W, ops: I) -> Result<(), QoiError>
+where
+    W: Write,
+    I: IntoIterator<Item = Op>,
+{
+    for op in ops {
+        op.encode(w)?;
+    }
+    Ok(())
+}
+
+/// Assembles a whole QOI file from a header and ops, followed by the end marker.
+pub fn assemble<I: IntoIterator<Item = Op>>(header: &Header, ops: I) -> Result<Vec<u8>, QoiError> {
+    let mut bytes = header.to_bytes().to_vec();
+    write_ops(&mut bytes, ops)?;
+    bytes.extend_from_slice(&END_MARKER);
+    Ok(bytes)
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use crate::consts::SEEN_PIXEL_ARRAY_SIZE;
+use crate::util::Pixel;
+
+/// Reduces an image to a palette before it's encoded, see `QoiEncoder::with_quantize`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quantize {
+    /// The most colours the palette can have, from 1 to 256. Up to 64 colours can each have their
+    /// own slot in QOI's index.
+    pub colors: u16,
+    /// Spread the error out with Floyd-Steinberg dithering, which avoids banding but breaks up
+    /// runs. Alpha is never dithered.
+    pub dither: bool,
+}
+
+impl Default for Quantize {
+    fn default() -> Self {
+        Quantize {
+            colors: SEEN_PIXEL_ARRAY_SIZE as u16,
+            dither: false,
+        }
+    }
+}
+
+type Colour = [u8; 4];
+
+impl Quantize {
+    //Maps every pixel of a packed RGB or RGBA buffer to the palette
+    pub(crate) fn apply(&self, buf: &[u8], width: u32, channels: usize) -> Vec<u8> {
+        let read = |chunk: &[u8]| [chunk[0], chunk[1], chunk[2], *chunk.get(3).unwrap_or(&255)];
+
+        let mut histogram = HashMap::new();
+        for chunk in buf.chunks(channels) {
+            *histogram.entry(read(chunk)).or_insert(0u64) += 1;
+        }
+        let colours = self.colors.clamp(1, 256) as usize;
+        //Images which already fit are left alone
+        if histogram.len() <= colours {
+            return buf.to_vec();
+        }
+
+        let mut histogram: Vec<(Colour, u64)> = histogram.into_iter().collect();
+        //Makes the palette the same every time, the map iterates in a random order
+        histogram.sort_unstable();
+        let palette = spread_hashes(median_cut(&mut histogram, colours));
+        let mut nearest_cache = HashMap::new();
+        let mut map_colour = |colour: Colour| {
+            *nearest_cache
+                .entry(colour)
+                .or_insert_with(|| nearest(&palette, colour))
+        };
+
+        let mut out = Vec::with_capacity(buf.len());
+        if !self.dither {
+            for chunk in buf.chunks(channels) {
+                out.extend_from_slice(&map_colour(read(chunk))[..channels]);
+            }
+            return out;
+        }
+
+        //The error carried into this row and the next one, with a pixel of padding on either side
+        let width = width as usize;
+        let mut errors = vec![[0f32; 3]; width + 2];
+        let mut next_errors = vec![[0f32; 3]; width + 2];
+        for row in buf.chunks(width * channels) {
+            for (x, chunk) in row.chunks(channels).enumerate() {
+                let colour = read(chunk);
+                let mut wanted = [0f32; 3];
+                let mut target = colour;
+                for c in 0..3 {
+                    wanted[c] = colour[c] as f32 + errors[x + 1][c];
+                    target[c] = wanted[c].round().clamp(0.0, 255.0) as u8;
+                }
+
+                let picked = map_colour(target);
+                for c in 0..3 {
+                    let error = wanted[c] - picked[c] as f32;
+                    errors[x + 2][c] += error * 7.0 / 16.0;
+                    next_errors[x][c] += error * 3.0 / 16.0;
+                    next_errors[x + 1][c] += error * 5.0 / 16.0;
+                    next_errors[x + 2][c] += error / 16.0;
+                }
+                out.extend_from_slice(&picked[..channels]);
+            }
+            errors = std::mem::replace(&mut next_errors, vec![[0f32; 3]; width + 2]);
+        }
+        out
+    }
+}
+
+//Splits the colours into boxes along their widest channel until there are enough boxes, the
+//palette is the average colour of each box
+fn median_cut(histogram: &mut [(Colour, u64)], colours: usize) -> Vec<Colour> {
+    let mut boxes = Vec::with_capacity(colours);
+    boxes.push(0..histogram.len());
+
+    while boxes.len() < colours {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.len() > 1)
+            .map(|(i, range)| {
+                let (width, channel) = widest_channel(&histogram[range.clone()]);
+                (width, i, channel)
+            })
+            .max();
+        let (i, channel) = match widest {
+            Some((width, i, channel)) if width > 0 => (i, channel),
+            _ => break,
+        };
+
+        let range = boxes[i].clone();
+        let colours = &mut histogram[range.clone()];
+        colours.sort_unstable_by_key(|(colour, _)| colour[channel]);
+
+        //Split where half of the pixels are on either side, keeping at least a colour in each
+        let total: u64 = colours.iter().map(|(_, count)| count).sum();
+        let mut seen = 0;
+        let split = colours
+            .iter()
+            .position(|(_, count)| {
+                seen += count;
+                seen * 2 >= total
+            })
+            .unwrap()
+            .clamp(1, colours.len() - 1);
+
+        boxes[i] = range.start..range.start + split;
+        boxes.push(range.start + split..range.end);
+    }
+
+    //Most used first, so they get first pick of the index slots
+    let mut palette: Vec<(Colour, u64)> = boxes
+        .into_iter()
+        .map(|range| average(&histogram[range]))
+        .collect();
+    palette.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    palette.into_iter().map(|(colour, _)| colour).collect()
+}
+
+fn widest_channel(colours: &[(Colour, u64)]) -> (u8, usize) {
+    (0..4)
+        .map(|c| {
+            let min = colours.iter().map(|(colour, _)| colour[c]).min().unwrap();
+            let max = colours.iter().map(|(colour, _)| colour[c]).max().unwrap();
+            (max - min, c)
+        })
+        .max()
+        .unwrap()
+}
+
+fn average(colours: &[(Colour, u64)]) -> (Colour, u64) {
+    let total: u64 = colours.iter().map(|(_, count)| count).sum();
+    let mut average = [0; 4];
+    for (c, channel) in average.iter_mut().enumerate() {
+        let sum: u64 = colours
+            .iter()
+            .map(|(colour, count)| colour[c] as u64 * count)
+            .sum();
+        *channel = ((sum + total / 2) / total) as u8;
+    }
+    (average, total)
+}
+
+//Nudges colours whose hash collides with a more used colour onto a free slot, so that every
+//colour can be encoded with OP_INDEX once it's been seen. Moving up to 3 on each of red, green
+//and blue can reach any slot
+fn spread_hashes(palette: Vec<Colour>) -> Vec<Colour> {
+    let mut offsets = Vec::new();
+    for dr in -3i16..=3 {
+        for dg in -3i16..=3 {
+            for db in -3i16..=3 {
+                offsets.push([dr, dg, db]);
+            }
+        }
+    }
+    offsets.sort_by_key(|offset| offset.iter().map(|d| d * d).sum::<i16>());
+
+    let hash = |colour: Colour| Pixel::new(colour[0], colour[1], colour[2], colour[3]).hash();
+    let mut taken = [false; SEEN_PIXEL_ARRAY_SIZE];
+    let mut spread: Vec<Colour> = Vec::with_capacity(palette.len());
+    for colour in palette {
+        let moved = offsets.iter().find_map(|offset| {
+            let mut moved = colour;
+            for c in 0..3 {
+                let channel = colour[c] as i16 + offset[c];
+                if !(0..=255).contains(&channel) {
+                    return None;
+                }
+                moved[c] = channel as u8;
+            }
+            Some(moved).filter(|&moved| !taken[hash(moved)] && !spread.contains(&moved))
+        });
+
+        //Once every slot is taken the colour might as well stay where it is
+        let colour = moved.unwrap_or(colour);
+        taken[hash(colour)] = true;
+        spread.push(colour);
+    }
+    spread
+}
+
+fn nearest(palette: &[Colour], colour: Colour) -> Colour {
+    *palette
+        .iter()
+        .min_by_key(|entry| {
+            entry
+                .iter()
+                .zip(&colour)
+                .map(|(&a, &b)| (a as i32 - b as i32).pow(2))
+                .sum::<i32>()
+        })
+        .unwrap()
+}
@@ -1,6 +1,7 @@
 use std::num::Wrapping;
 
 use crate::consts::SEEN_PIXEL_ARRAY_SIZE;
+use crate::error::QoiError;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub(crate) struct Pixel {
@@ -39,6 +40,12 @@ impl Pixel {
         self.a
     }
 
+    //Writes the pixel as RGB or RGBA depending on how long buf is
+    #[inline]
+    pub(crate) fn write_to(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(&[self.r, self.g, self.b, self.a][..buf.len()]);
+    }
+
     pub(crate) fn hash(&self) -> usize {
         (Wrapping(self.r()) * Wrapping(3)
             + Wrapping(self.g()) * Wrapping(5)
@@ -48,3 +55,18 @@ impl Pixel {
             % SEEN_PIXEL_ARRAY_SIZE
     }
 }
+
+//The smallest buffer which holds height rows of row_size bytes, each starting stride bytes after
+//the last. The last row doesn't need any padding, so a buffer can be a view into a larger image
+pub(crate) fn strided_len(row_size: usize, height: u32, stride: usize) -> Result<usize, QoiError> {
+    if stride < row_size {
+        return Err(QoiError::InvalidStride { stride, row_size });
+    }
+    match height {
+        0 => Ok(0),
+        height => (height as usize - 1)
+            .checked_mul(stride)
+            .and_then(|len| len.checked_add(row_size))
+            .ok_or(QoiError::InvalidStride { stride, row_size }),
+    }
+}
@@ -0,0 +1,144 @@
+use crate::codec::QoiCodecState;
+use crate::consts::{END_MARKER, MAX_RUN_LENGTH, RGBA_CHANNELS, RGB_CHANNELS};
+use crate::error::QoiError;
+use crate::header::Header;
+use crate::ops::{Op, QoiTokenizer};
+use crate::util::Pixel;
+
+/// What the reference encoder, qoi.h, would have written instead of an op.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Canonical {
+    Op(Op),
+    /// The pixels should have been part of the run before them.
+    ExtendRun,
+}
+
+/// A legal op which isn't what the reference encoder would have written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Suboptimal {
+    /// Byte offset of the op from the start of the file.
+    pub offset: usize,
+    /// Index of the first pixel the op produced.
+    pub pixel: u64,
+    pub found: Op,
+    pub canonical: Canonical,
+}
+
+/// Everything wrong with a QOI file.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// The header, if it was valid.
+    pub header: Option<Header>,
+    /// Ways the file breaks the spec.
+    pub errors: Vec<QoiError>,
+    /// Bytes after the end marker.
+    pub trailing_data: usize,
+    pub suboptimal: Vec<Suboptimal>,
+}
+
+impl VerifyReport {
+    /// Whether the file follows the spec, it may still be encoded suboptimally.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty() && self.trailing_data == 0
+    }
+}
+
+/// Checks a QOI file against the spec, and compares every op with what the reference encoder
+/// would have written. Like qoi.h, pixels in a run aren't added to the index, so index ops which
+/// rely on them are flagged, this crate's encoder writes some of those.
+pub fn verify(data: &[u8]) -> VerifyReport {
+    let mut report = VerifyReport::default();
+    let mut tokenizer = match QoiTokenizer::new(data) {
+        Ok(tokenizer) => tokenizer,
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    };
+    let header = tokenizer.header();
+    report.header = Some(header);
+
+    //Tracks the reference encoder, which only differs from the decoder by not indexing runs
+    let mut encoder_state = QoiCodecState::new();
+    let mut process_pixel = |pixel: Pixel| match header.channels {
+        RGB_CHANNELS => encoder_state.process_pixel::<RGB_CHANNELS>(pixel),
+        _ => encoder_state.process_pixel::<RGBA_CHANNELS>(pixel),
+    };
+    let initial_state = QoiCodecState::new();
+
+    let mut last_run = None;
+    let mut pixels = 0;
+    //Where the pixels would end if the ops stopped at the end marker at the end of the file
+    let mut pixels_before_end = None;
+    let end_marker_start = data.len().saturating_sub(END_MARKER.len());
+
+    //Running into the end marker at the end of the file means there weren't enough ops
+    let too_few_pixels = |pixels_before_end: Option<u64>| {
+        pixels_before_end
+            .filter(|_| data.ends_with(&END_MARKER))
+            .map(|actual| QoiError::PixelCountMismatch {
+                expected: header.pixels(),
+                actual,
+            })
+    };
+
+    for token in tokenizer.by_ref() {
+        let token = match token {
+            Ok(token) => token,
+            Err(e) => {
+                report
+                    .errors
+                    .push(too_few_pixels(pixels_before_end).unwrap_or(e));
+                return report;
+            }
+        };
+        if token.offset >= end_marker_start && pixels_before_end.is_none() {
+            pixels_before_end = Some(token.pixel);
+        }
+        pixels = token.pixel + token.count;
+
+        let [r, g, b, a] = token.color;
+        let pixel = Pixel::new(r, g, b, a);
+        let canonical = if let Op::Run(run) = token.op {
+            //Runs repeat the last pixel and qoi.h doesn't index them, so the encoder skips them. A
+            //run only needs to be split once it's too long for one op
+            let split = last_run.is_some_and(|last| last < MAX_RUN_LENGTH);
+            last_run = Some(run);
+            Some(Canonical::ExtendRun).filter(|_| split)
+        } else {
+            last_run = None;
+            //The pixel can also end the encoder's run, which doesn't matter here
+            let canonical = process_pixel(pixel)
+                .map(|chunk| Op::from(&chunk.resolve(&initial_state)))
+                .find(|op| !matches!(op, Op::Run(_)))
+                .map_or(Canonical::ExtendRun, Canonical::Op);
+            Some(canonical).filter(|&canonical| canonical != Canonical::Op(token.op))
+        };
+
+        if let Some(canonical) = canonical {
+            report.suboptimal.push(Suboptimal {
+                offset: token.offset,
+                pixel: token.pixel,
+                found: token.op,
+                canonical,
+            });
+        }
+    }
+
+    if pixels > header.pixels() {
+        report.errors.push(QoiError::PixelCountMismatch {
+            expected: header.pixels(),
+            actual: pixels,
+        });
+    }
+
+    let end = tokenizer.offset();
+    if data.get(end..end + END_MARKER.len()) == Some(&END_MARKER[..]) {
+        report.trailing_data = data.len() - end - END_MARKER.len();
+    } else {
+        let e = too_few_pixels(pixels_before_end).unwrap_or(QoiError::MissingEndMarker);
+        report.errors.push(e);
+    }
+    report
+}